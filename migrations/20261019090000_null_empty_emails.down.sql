@@ -0,0 +1,3 @@
+-- Add down migration script here
+
+-- Empty emails are not restored: NULL is the only valid "no email" value.
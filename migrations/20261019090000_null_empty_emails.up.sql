@@ -0,0 +1,3 @@
+-- Add up migration script here
+
+UPDATE users SET email = NULL WHERE TRIM(email) = '';
@@ -0,0 +1,17 @@
+-- Add down migration script here
+
+DROP INDEX users_username_key;
+DROP INDEX users_email_key;
+DROP INDEX categories_category_name_key;
+DROP INDEX products_product_name_key;
+
+ALTER TABLE users ADD CONSTRAINT users_username_key UNIQUE (username);
+ALTER TABLE users ADD CONSTRAINT users_email_key UNIQUE (email);
+ALTER TABLE categories ADD CONSTRAINT categories_category_name_key UNIQUE (category_name);
+ALTER TABLE products ADD CONSTRAINT products_product_name_key UNIQUE (product_name);
+
+ALTER TABLE users DROP COLUMN deleted_at;
+ALTER TABLE categories DROP COLUMN deleted_at;
+ALTER TABLE products DROP COLUMN deleted_at;
+ALTER TABLE ratings DROP COLUMN deleted_at;
+ALTER TABLE purchases DROP COLUMN deleted_at;
@@ -0,0 +1,19 @@
+-- Add up migration script here
+
+ALTER TABLE users ADD COLUMN deleted_at TIMESTAMP WITH TIME ZONE;
+ALTER TABLE categories ADD COLUMN deleted_at TIMESTAMP WITH TIME ZONE;
+ALTER TABLE products ADD COLUMN deleted_at TIMESTAMP WITH TIME ZONE;
+ALTER TABLE ratings ADD COLUMN deleted_at TIMESTAMP WITH TIME ZONE;
+ALTER TABLE purchases ADD COLUMN deleted_at TIMESTAMP WITH TIME ZONE;
+
+-- Names only need to be unique among live rows, so a deleted row does not
+-- block re-creating it.
+ALTER TABLE users DROP CONSTRAINT users_username_key;
+ALTER TABLE users DROP CONSTRAINT users_email_key;
+ALTER TABLE categories DROP CONSTRAINT categories_category_name_key;
+ALTER TABLE products DROP CONSTRAINT products_product_name_key;
+
+CREATE UNIQUE INDEX users_username_key ON users (username) WHERE deleted_at IS NULL;
+CREATE UNIQUE INDEX users_email_key ON users (email) WHERE deleted_at IS NULL;
+CREATE UNIQUE INDEX categories_category_name_key ON categories (category_name) WHERE deleted_at IS NULL;
+CREATE UNIQUE INDEX products_product_name_key ON products (product_name) WHERE deleted_at IS NULL;
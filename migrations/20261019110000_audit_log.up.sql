@@ -0,0 +1,15 @@
+-- Add up migration script here
+
+CREATE TABLE audit_log (
+    id UUID PRIMARY KEY NOT NULL DEFAULT (uuid_generate_v4()),
+    actor VARCHAR(100) NOT NULL,
+    action VARCHAR(20) NOT NULL,
+    resource_type VARCHAR(50) NOT NULL,
+    resource_id UUID NOT NULL,
+    before JSONB,
+    after JSONB,
+    request_id UUID NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX audit_log_resource_idx ON audit_log (resource_type, resource_id, created_at);
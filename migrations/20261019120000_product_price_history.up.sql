@@ -0,0 +1,14 @@
+-- Add up migration script here
+
+CREATE TABLE product_price_history (
+    id UUID PRIMARY KEY NOT NULL DEFAULT (uuid_generate_v4()),
+    product_id UUID NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    price NUMERIC(19, 2) NOT NULL,
+    valid_from TIMESTAMP WITH TIME ZONE NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX product_price_history_product_idx ON product_price_history (product_id, valid_from);
+
+INSERT INTO product_price_history (product_id, price, valid_from)
+SELECT id, price, COALESCE(created_at, NOW()) FROM products;
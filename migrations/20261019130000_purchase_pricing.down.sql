@@ -0,0 +1,8 @@
+-- Add down migration script here
+
+DROP INDEX purchases_created_at_idx;
+
+ALTER TABLE purchases DROP COLUMN total;
+ALTER TABLE purchases DROP COLUMN currency;
+ALTER TABLE purchases DROP COLUMN unit_price;
+ALTER TABLE purchases DROP COLUMN quantity;
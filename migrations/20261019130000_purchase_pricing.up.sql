@@ -0,0 +1,20 @@
+-- Add up migration script here
+
+ALTER TABLE purchases ADD COLUMN quantity INT NOT NULL DEFAULT 1 CHECK (quantity > 0);
+ALTER TABLE purchases ADD COLUMN unit_price NUMERIC(19, 2);
+ALTER TABLE purchases ADD COLUMN currency CHAR(3) NOT NULL DEFAULT 'USD';
+
+-- Existing purchases take the price that was current when they were made.
+UPDATE purchases p SET unit_price = COALESCE(
+    (
+        SELECT h.price FROM product_price_history h
+        WHERE h.product_id = p.product_id AND h.valid_from <= p.created_at
+        ORDER BY h.valid_from DESC LIMIT 1
+    ),
+    (SELECT price FROM products WHERE id = p.product_id)
+);
+
+ALTER TABLE purchases ALTER COLUMN unit_price SET NOT NULL;
+ALTER TABLE purchases ADD COLUMN total NUMERIC(19, 2) NOT NULL GENERATED ALWAYS AS (quantity * unit_price) STORED;
+
+CREATE INDEX purchases_created_at_idx ON purchases (created_at);
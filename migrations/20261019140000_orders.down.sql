@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+DROP TABLE order_items;
+DROP TABLE orders;
@@ -0,0 +1,24 @@
+-- Add up migration script here
+
+CREATE TABLE orders (
+    id UUID PRIMARY KEY NOT NULL DEFAULT (uuid_generate_v4()),
+    user_id UUID NOT NULL REFERENCES users(id),
+    currency CHAR(3) NOT NULL,
+    total NUMERIC(19, 2) NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE TABLE order_items (
+    id UUID PRIMARY KEY NOT NULL DEFAULT (uuid_generate_v4()),
+    order_id UUID NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    product_id UUID NOT NULL REFERENCES products(id),
+    quantity INT NOT NULL CHECK (quantity > 0),
+    unit_price NUMERIC(19, 2) NOT NULL,
+    total NUMERIC(19, 2) NOT NULL GENERATED ALWAYS AS (quantity * unit_price) STORED,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    UNIQUE (order_id, product_id)
+);
+
+CREATE INDEX orders_user_id_idx ON orders (user_id, created_at);
+CREATE INDEX order_items_product_id_idx ON order_items (product_id);
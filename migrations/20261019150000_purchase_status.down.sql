@@ -0,0 +1,6 @@
+-- Add down migration script here
+
+DROP TABLE purchase_status_transitions;
+
+DROP INDEX purchases_status_idx;
+ALTER TABLE purchases DROP COLUMN status;
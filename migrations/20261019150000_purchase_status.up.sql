@@ -0,0 +1,17 @@
+-- Add up migration script here
+
+ALTER TABLE purchases ADD COLUMN status VARCHAR(20) NOT NULL DEFAULT 'pending'
+    CHECK (status IN ('pending', 'paid', 'shipped', 'delivered', 'cancelled', 'refunded'));
+
+CREATE TABLE purchase_status_transitions (
+    id UUID PRIMARY KEY NOT NULL DEFAULT (uuid_generate_v4()),
+    purchase_id UUID NOT NULL REFERENCES purchases(id) ON DELETE CASCADE,
+    from_status VARCHAR(20) NOT NULL,
+    to_status VARCHAR(20) NOT NULL,
+    actor VARCHAR(100) NOT NULL,
+    reason TEXT,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX purchases_status_idx ON purchases (status);
+CREATE INDEX purchase_status_transitions_purchase_idx ON purchase_status_transitions (purchase_id, created_at);
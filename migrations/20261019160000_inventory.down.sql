@@ -0,0 +1,5 @@
+-- Add down migration script here
+
+DROP TABLE stock_adjustments;
+
+ALTER TABLE products DROP COLUMN stock_quantity;
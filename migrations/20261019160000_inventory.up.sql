@@ -0,0 +1,16 @@
+-- Add up migration script here
+
+ALTER TABLE products ADD COLUMN stock_quantity INTEGER NOT NULL DEFAULT 0
+    CHECK (stock_quantity >= 0);
+
+CREATE TABLE stock_adjustments (
+    id UUID PRIMARY KEY NOT NULL DEFAULT (uuid_generate_v4()),
+    product_id UUID NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    delta INTEGER NOT NULL CHECK (delta <> 0),
+    quantity_after INTEGER NOT NULL,
+    reason TEXT NOT NULL,
+    actor VARCHAR(100) NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX stock_adjustments_product_idx ON stock_adjustments (product_id, created_at);
@@ -0,0 +1,10 @@
+-- Add down migration script here
+
+DROP TABLE stock_transfers;
+
+ALTER TABLE stock_adjustments DROP COLUMN warehouse_id;
+ALTER TABLE order_items DROP COLUMN warehouse_id;
+ALTER TABLE purchases DROP COLUMN warehouse_id;
+
+DROP TABLE warehouse_stock;
+DROP TABLE warehouses;
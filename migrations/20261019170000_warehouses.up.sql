@@ -0,0 +1,53 @@
+-- Add up migration script here
+
+CREATE TABLE warehouses (
+    id UUID PRIMARY KEY NOT NULL DEFAULT (uuid_generate_v4()),
+    warehouse_name VARCHAR(100) NOT NULL UNIQUE,
+    latitude DOUBLE PRECISION NOT NULL CHECK (latitude BETWEEN -90 AND 90),
+    longitude DOUBLE PRECISION NOT NULL CHECK (longitude BETWEEN -180 AND 180),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE TABLE warehouse_stock (
+    warehouse_id UUID NOT NULL REFERENCES warehouses(id),
+    product_id UUID NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    quantity INTEGER NOT NULL DEFAULT 0 CHECK (quantity >= 0),
+    PRIMARY KEY (warehouse_id, product_id)
+);
+
+CREATE INDEX warehouse_stock_product_idx ON warehouse_stock (product_id);
+
+-- Existing stock, purchases and order lines are attributed to a single
+-- warehouse so that products.stock_quantity stays the sum of all locations.
+INSERT INTO warehouses (warehouse_name, latitude, longitude) VALUES ('main', 0, 0);
+
+INSERT INTO warehouse_stock (warehouse_id, product_id, quantity)
+    SELECT (SELECT id FROM warehouses WHERE warehouse_name = 'main'), id, stock_quantity
+    FROM products WHERE stock_quantity > 0;
+
+ALTER TABLE purchases ADD COLUMN warehouse_id UUID REFERENCES warehouses(id);
+UPDATE purchases SET warehouse_id = (SELECT id FROM warehouses WHERE warehouse_name = 'main');
+ALTER TABLE purchases ALTER COLUMN warehouse_id SET NOT NULL;
+
+ALTER TABLE order_items ADD COLUMN warehouse_id UUID REFERENCES warehouses(id);
+UPDATE order_items SET warehouse_id = (SELECT id FROM warehouses WHERE warehouse_name = 'main');
+ALTER TABLE order_items ALTER COLUMN warehouse_id SET NOT NULL;
+
+ALTER TABLE stock_adjustments ADD COLUMN warehouse_id UUID REFERENCES warehouses(id);
+UPDATE stock_adjustments SET warehouse_id = (SELECT id FROM warehouses WHERE warehouse_name = 'main');
+ALTER TABLE stock_adjustments ALTER COLUMN warehouse_id SET NOT NULL;
+
+CREATE TABLE stock_transfers (
+    id UUID PRIMARY KEY NOT NULL DEFAULT (uuid_generate_v4()),
+    product_id UUID NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    from_warehouse_id UUID NOT NULL REFERENCES warehouses(id),
+    to_warehouse_id UUID NOT NULL REFERENCES warehouses(id),
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    reason TEXT,
+    actor VARCHAR(100) NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    CHECK (from_warehouse_id <> to_warehouse_id)
+);
+
+CREATE INDEX stock_transfers_product_idx ON stock_transfers (product_id, created_at);
@@ -0,0 +1,17 @@
+-- Add up migration script here
+
+CREATE TABLE cart_items (
+    id UUID PRIMARY KEY NOT NULL DEFAULT (uuid_generate_v4()),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    product_id UUID NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    -- Set while the line holds reserved stock taken from this warehouse.
+    warehouse_id UUID REFERENCES warehouses(id),
+    reserved_until TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    UNIQUE (user_id, product_id),
+    CHECK ((warehouse_id IS NULL) = (reserved_until IS NULL))
+);
+
+CREATE INDEX cart_items_reserved_until_idx ON cart_items (reserved_until) WHERE reserved_until IS NOT NULL;
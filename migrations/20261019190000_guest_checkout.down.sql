@@ -0,0 +1,13 @@
+-- Add down migration script here
+
+DELETE FROM purchases WHERE user_id IS NULL;
+
+DROP INDEX purchases_unclaimed_guest_email_idx;
+ALTER TABLE purchases DROP CONSTRAINT purchases_owner_check;
+ALTER TABLE purchases DROP COLUMN claim_token;
+ALTER TABLE purchases DROP COLUMN guest_email;
+ALTER TABLE purchases ALTER COLUMN user_id SET NOT NULL;
+
+DROP TABLE email_verifications;
+
+ALTER TABLE users DROP COLUMN email_verified_at;
@@ -0,0 +1,20 @@
+-- Add up migration script here
+
+ALTER TABLE users ADD COLUMN email_verified_at TIMESTAMP WITH TIME ZONE;
+
+CREATE TABLE email_verifications (
+    token UUID PRIMARY KEY NOT NULL DEFAULT (uuid_generate_v4()),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    email VARCHAR(255) NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX email_verifications_user_idx ON email_verifications (user_id);
+
+ALTER TABLE purchases ALTER COLUMN user_id DROP NOT NULL;
+ALTER TABLE purchases ADD COLUMN guest_email VARCHAR(255);
+ALTER TABLE purchases ADD COLUMN claim_token UUID UNIQUE;
+ALTER TABLE purchases ADD CONSTRAINT purchases_owner_check
+    CHECK (user_id IS NOT NULL OR guest_email IS NOT NULL);
+
+CREATE INDEX purchases_unclaimed_guest_email_idx ON purchases (LOWER(guest_email)) WHERE user_id IS NULL;
@@ -0,0 +1,3 @@
+-- Add down migration script here
+
+DROP INDEX ratings_user_product_key;
@@ -0,0 +1,13 @@
+-- Add up migration script here
+
+-- Keep only the most recently updated live rating per user and product.
+UPDATE ratings SET deleted_at = NOW()
+WHERE deleted_at IS NULL AND id IN (
+    SELECT id FROM (
+        SELECT id, ROW_NUMBER() OVER (PARTITION BY user_id, product_id ORDER BY updated_at DESC, created_at DESC) AS position
+        FROM ratings WHERE deleted_at IS NULL
+    ) ranked
+    WHERE position > 1
+);
+
+CREATE UNIQUE INDEX ratings_user_product_key ON ratings (user_id, product_id) WHERE deleted_at IS NULL;
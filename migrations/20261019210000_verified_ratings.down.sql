@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+DROP INDEX ratings_verified_purchase_idx;
+ALTER TABLE ratings DROP COLUMN verified_purchase;
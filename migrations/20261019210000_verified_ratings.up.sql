@@ -0,0 +1,14 @@
+-- Add up migration script here
+
+ALTER TABLE ratings ADD COLUMN verified_purchase BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE ratings r SET verified_purchase = EXISTS (
+    SELECT 1 FROM purchases p
+    WHERE p.user_id = r.user_id AND p.product_id = r.product_id
+        AND p.deleted_at IS NULL AND p.status <> 'cancelled'
+) OR EXISTS (
+    SELECT 1 FROM order_items i JOIN orders o ON o.id = i.order_id
+    WHERE o.user_id = r.user_id AND i.product_id = r.product_id
+);
+
+CREATE INDEX ratings_verified_purchase_idx ON ratings (product_id, verified_purchase) WHERE deleted_at IS NULL;
@@ -0,0 +1,8 @@
+-- Add down migration script here
+
+DROP INDEX ratings_moderation_queue_idx;
+ALTER TABLE ratings DROP COLUMN moderated_at;
+ALTER TABLE ratings DROP COLUMN moderation_reason;
+ALTER TABLE ratings DROP COLUMN moderation_status;
+ALTER TABLE ratings DROP COLUMN body;
+ALTER TABLE ratings DROP COLUMN title;
@@ -0,0 +1,10 @@
+-- Add up migration script here
+
+ALTER TABLE ratings ADD COLUMN title VARCHAR(200);
+ALTER TABLE ratings ADD COLUMN body TEXT;
+ALTER TABLE ratings ADD COLUMN moderation_status VARCHAR(20) NOT NULL DEFAULT 'approved'
+    CHECK (moderation_status IN ('pending', 'approved', 'rejected'));
+ALTER TABLE ratings ADD COLUMN moderation_reason TEXT;
+ALTER TABLE ratings ADD COLUMN moderated_at TIMESTAMP WITH TIME ZONE;
+
+CREATE INDEX ratings_moderation_queue_idx ON ratings (created_at) WHERE moderation_status = 'pending' AND deleted_at IS NULL;
@@ -0,0 +1,8 @@
+-- Add down migration script here
+
+DROP INDEX ratings_helpful_score_idx;
+ALTER TABLE ratings DROP COLUMN helpful_score;
+ALTER TABLE ratings DROP COLUMN unhelpful_count;
+ALTER TABLE ratings DROP COLUMN helpful_count;
+
+DROP TABLE rating_votes;
@@ -0,0 +1,26 @@
+-- Add up migration script here
+
+CREATE TABLE rating_votes (
+    id UUID PRIMARY KEY NOT NULL DEFAULT (uuid_generate_v4()),
+    rating_id UUID NOT NULL REFERENCES ratings(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    vote VARCHAR(4) NOT NULL CHECK (vote IN ('up', 'down')),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    UNIQUE (rating_id, user_id)
+);
+
+ALTER TABLE ratings ADD COLUMN helpful_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE ratings ADD COLUMN unhelpful_count INTEGER NOT NULL DEFAULT 0;
+
+-- Lower bound of the Wilson score interval (95%) for the share of helpful
+-- votes, so a review with 40 of 50 up votes outranks one with 2 of 2.
+ALTER TABLE ratings ADD COLUMN helpful_score DOUBLE PRECISION NOT NULL GENERATED ALWAYS AS (
+    CASE WHEN helpful_count + unhelpful_count = 0 THEN 0 ELSE
+        ((helpful_count + 1.9208) / (helpful_count + unhelpful_count)
+            - 1.96 * SQRT(helpful_count::DOUBLE PRECISION * unhelpful_count / (helpful_count + unhelpful_count) + 0.9604) / (helpful_count + unhelpful_count))
+        / (1 + 3.8416 / (helpful_count + unhelpful_count))
+    END
+) STORED;
+
+CREATE INDEX ratings_helpful_score_idx ON ratings (helpful_score DESC);
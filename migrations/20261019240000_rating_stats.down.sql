@@ -0,0 +1,3 @@
+-- Add down migration script here
+
+DROP MATERIALIZED VIEW product_rating_stats;
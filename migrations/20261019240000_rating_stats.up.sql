@@ -0,0 +1,21 @@
+-- Add up migration script here
+
+-- Per-product aggregates over approved, live ratings. Refreshed periodically
+-- by the server, so it may lag the ratings table by one refresh interval.
+CREATE MATERIALIZED VIEW product_rating_stats AS
+    SELECT
+        product_id,
+        COUNT(*) AS rating_count,
+        SUM(rating) AS rating_sum,
+        COUNT(*) FILTER (WHERE rating = 1) AS count_1,
+        COUNT(*) FILTER (WHERE rating = 2) AS count_2,
+        COUNT(*) FILTER (WHERE rating = 3) AS count_3,
+        COUNT(*) FILTER (WHERE rating = 4) AS count_4,
+        COUNT(*) FILTER (WHERE rating = 5) AS count_5,
+        MAX(updated_at) AS last_rated_at
+    FROM ratings
+    WHERE deleted_at IS NULL AND moderation_status = 'approved'
+    GROUP BY product_id;
+
+-- Required for REFRESH MATERIALIZED VIEW CONCURRENTLY.
+CREATE UNIQUE INDEX product_rating_stats_product_id_key ON product_rating_stats (product_id);
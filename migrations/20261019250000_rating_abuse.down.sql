@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+DROP INDEX ratings_abuse_flags_idx;
+ALTER TABLE ratings DROP COLUMN abuse_flags;
@@ -0,0 +1,6 @@
+-- Add up migration script here
+
+-- Names of the abuse rules that fired when the rating was created.
+ALTER TABLE ratings ADD COLUMN abuse_flags TEXT[] NOT NULL DEFAULT '{}';
+
+CREATE INDEX ratings_abuse_flags_idx ON ratings (product_id, created_at) WHERE cardinality(abuse_flags) > 0;
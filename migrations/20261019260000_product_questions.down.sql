@@ -0,0 +1,6 @@
+-- Add down migration script here
+
+DROP TABLE product_answer_votes;
+DROP TABLE product_question_votes;
+DROP TABLE product_answers;
+DROP TABLE product_questions;
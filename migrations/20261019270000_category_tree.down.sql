@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+DROP INDEX categories_parent_id_idx;
+ALTER TABLE categories DROP COLUMN parent_id;
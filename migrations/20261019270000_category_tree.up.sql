@@ -0,0 +1,6 @@
+-- Add up migration script here
+
+ALTER TABLE categories ADD COLUMN parent_id UUID REFERENCES categories(id) ON DELETE SET NULL;
+ALTER TABLE categories ADD CONSTRAINT categories_parent_check CHECK (parent_id <> id);
+
+CREATE INDEX categories_parent_id_idx ON categories (parent_id);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+DROP TABLE product_tags;
+DROP TABLE tags;
@@ -0,0 +1,109 @@
+use std::future::{ready, Future, Ready};
+
+use actix_web::{dev::{Payload, Service, ServiceRequest, ServiceResponse}, http::header::{HeaderName, HeaderValue}, FromRequest, HttpMessage, HttpRequest};
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::auth;
+
+
+pub const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+pub const X_USER_ID: HeaderName = HeaderName::from_static("x-user-id");
+
+#[derive(Debug, Clone, Copy)]
+pub struct RequestId(pub Uuid);
+
+/// Tags every request with an id, reusing a valid incoming `X-Request-Id`,
+/// and echoes it on the response so audit entries can be correlated.
+pub fn assign_request_id<S, B>(req: ServiceRequest, srv: &S) -> impl Future<Output = Result<ServiceResponse<B>, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>
+{
+    let request_id = req
+        .headers()
+        .get(X_REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| Uuid::parse_str(value).ok())
+        .unwrap_or_else(Uuid::new_v4);
+    req.extensions_mut().insert(RequestId(request_id));
+
+    let response = srv.call(req);
+    async move {
+        let mut response = response.await?;
+        if let Ok(value) = HeaderValue::from_str(&request_id.to_string()) {
+            response.headers_mut().insert(X_REQUEST_ID, value);
+        }
+        Ok(response)
+    }
+}
+
+/// Who is performing a mutation and under which request.
+///
+/// The actor is `admin` for requests carrying the admin token, otherwise the
+/// `X-User-Id` header when it is a valid id, otherwise `anonymous`. Nothing
+/// authenticates that header: a `user:` actor records who the caller claimed
+/// to be, as passed on by the upstream gateway, not a verified identity.
+#[derive(Debug, Clone)]
+pub struct AuditContext {
+    pub actor: String,
+    pub request_id: Uuid
+}
+
+impl FromRequest for AuditContext {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let actor = if auth::is_admin(req) {
+            "admin".to_string()
+        } else {
+            req.headers()
+                .get(X_USER_ID)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| Uuid::parse_str(value).ok())
+                .map(|user_id| format!("user:{}", user_id))
+                .unwrap_or_else(|| "anonymous".to_string())
+        };
+        let request_id = req
+            .extensions()
+            .get::<RequestId>()
+            .map(|request_id| request_id.0)
+            .unwrap_or_else(Uuid::new_v4);
+
+        ready(Ok(AuditContext { actor, request_id }))
+    }
+}
+
+/// Serializes a row for the `before`/`after` columns.
+pub fn snapshot<T: Serialize>(resource: &T) -> Option<Value> {
+    serde_json::to_value(resource).ok()
+}
+
+/// Writes an audit entry inside the caller's transaction, so it commits or
+/// rolls back together with the mutation it describes.
+pub async fn record(
+    tx: &mut Transaction<'_, Postgres>,
+    ctx: &AuditContext,
+    action: &str,
+    resource_type: &str,
+    resource_id: Uuid,
+    before: Option<Value>,
+    after: Option<Value>
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO audit_log (actor, action, resource_type, resource_id, before, after, request_id) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        ctx.actor,
+        action,
+        resource_type,
+        resource_id,
+        before,
+        after,
+        ctx.request_id
+    )
+        .execute(tx)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,82 @@
+use std::future::{ready, Ready};
+
+use actix_web::{dev::Payload, error, http::{header, StatusCode}, web, FromRequest, HttpRequest};
+
+use uuid::Uuid;
+
+use crate::{audit::X_USER_ID, extractors::error_response, AppState};
+
+
+/// Guards admin-only endpoints. The request must carry
+/// `Authorization: Bearer <ADMIN_TOKEN>`; without a configured token every
+/// admin request is refused.
+#[derive(Debug)]
+pub struct Admin;
+
+impl FromRequest for Admin {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let result = match bearer_token(req) {
+            Some(_) if is_admin(req) => Ok(Admin),
+            None => Err(rejection(StatusCode::UNAUTHORIZED, "Admin credentials are required")),
+            Some(_) => Err(rejection(StatusCode::FORBIDDEN, "Admin credentials are invalid"))
+        };
+
+        ready(result)
+    }
+}
+
+/// The caller's own user id, taken from the `X-User-Id` header set by the
+/// upstream gateway. Backs the `/me` endpoints.
+#[derive(Debug, Clone, Copy)]
+pub struct CurrentUser(pub Uuid);
+
+impl CurrentUser {
+    /// Whether the caller is acting as `user_id`, for `/users/{id}/...`
+    /// endpoints that only the user themselves may use.
+    pub fn is(&self, user_id: Uuid) -> bool {
+        self.0 == user_id
+    }
+}
+
+impl FromRequest for CurrentUser {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let result = req
+            .headers()
+            .get(X_USER_ID)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| Uuid::parse_str(value).ok())
+            .map(CurrentUser)
+            .ok_or_else(|| rejection(StatusCode::UNAUTHORIZED, "A valid X-User-Id header is required"));
+
+        ready(result)
+    }
+}
+
+/// Whether the request carries the configured admin token.
+pub fn is_admin(req: &HttpRequest) -> bool {
+    let expected = req
+        .app_data::<web::Data<AppState>>()
+        .and_then(|data| data.config.admin_token.as_deref());
+
+    match (expected, bearer_token(req)) {
+        (Some(expected), Some(provided)) => expected == provided,
+        _ => false
+    }
+}
+
+fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+fn rejection(status: StatusCode, message: &str) -> actix_web::Error {
+    error::InternalError::from_response(message.to_string(), error_response(status, message.to_string())).into()
+}
@@ -0,0 +1,79 @@
+use std::str::FromStr;
+use crate::{inventory::FulfilmentStrategy, moderation::{AbuseAction, AbuseRules, PurchasePolicy}};
+
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub database_url: String,
+    pub json_limit: usize,
+    pub payload_limit: usize,
+    pub categories_cache_control: String,
+    pub products_cache_control: String,
+    pub admin_token: Option<String>,
+    pub retention_days: i64,
+    pub purge_interval_secs: u64,
+    pub currency: String,
+    pub fulfilment_strategy: FulfilmentStrategy,
+    /// How long cart lines hold reserved stock; `0` disables reservations.
+    pub cart_reservation_ttl_secs: i64,
+    pub reservation_sweep_interval_secs: u64,
+    pub rating_purchase_policy: PurchasePolicy,
+    /// Lowercased words that send a review to the moderation queue.
+    pub banned_words: Vec<String>,
+    pub rating_stats_refresh_secs: u64,
+    /// How many votes' worth of the catalogue-wide mean the Bayesian score
+    /// starts from; higher values need more ratings to move a product.
+    pub rating_prior_weight: f64,
+    pub abuse_rules: AbuseRules
+}
+
+impl Config {
+    pub fn init() -> Config {
+        let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+
+        Config {
+            database_url,
+            json_limit: env_or("JSON_LIMIT", 64 * 1024),
+            payload_limit: env_or("PAYLOAD_LIMIT", 256 * 1024),
+            categories_cache_control: env_or("CATEGORIES_CACHE_CONTROL", "public, max-age=300".to_string()),
+            products_cache_control: env_or("PRODUCTS_CACHE_CONTROL", "public, max-age=60".to_string()),
+            admin_token: std::env::var("ADMIN_TOKEN").ok(),
+            retention_days: env_or("SOFT_DELETE_RETENTION_DAYS", 30),
+            purge_interval_secs: env_or("PURGE_INTERVAL_SECS", 60 * 60),
+            currency: env_or("CURRENCY", "USD".to_string()),
+            fulfilment_strategy: env_or("FULFILMENT_STRATEGY", FulfilmentStrategy::MostStock),
+            cart_reservation_ttl_secs: env_or("CART_RESERVATION_TTL_SECS", 0),
+            reservation_sweep_interval_secs: env_or("RESERVATION_SWEEP_INTERVAL_SECS", 60),
+            rating_purchase_policy: env_or("RATING_PURCHASE_POLICY", PurchasePolicy::Flag),
+            banned_words: std::env::var("BANNED_WORDS")
+                .map(|words| {
+                    words
+                        .split(',')
+                        .map(|word| word.trim().to_lowercase())
+                        .filter(|word| !word.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            rating_stats_refresh_secs: env_or("RATING_STATS_REFRESH_SECS", 5 * 60),
+            rating_prior_weight: env_or("RATING_PRIOR_WEIGHT", 10.0),
+            abuse_rules: AbuseRules {
+                min_account_age_hours: env_or("RATING_MIN_ACCOUNT_AGE_HOURS", 24),
+                max_ratings_per_hour: env_or("RATING_MAX_PER_HOUR", 5),
+                spike_threshold: env_or("RATING_SPIKE_THRESHOLD", 20),
+                spike_window_mins: env_or("RATING_SPIKE_WINDOW_MINS", 60),
+                action: env_or("RATING_ABUSE_ACTION", AbuseAction::Quarantine)
+            }
+        }
+    }
+}
+
+/// Reads `key` from the environment, falling back to `default` when it is unset.
+/// Panics at startup if the variable is set but cannot be parsed.
+fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    match std::env::var(key) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{} has an invalid value: {}", key, value)),
+        Err(_) => default
+    }
+}
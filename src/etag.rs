@@ -0,0 +1,83 @@
+use std::{collections::hash_map::DefaultHasher, hash::{Hash, Hasher}, time::SystemTime};
+
+use actix_web::{http::header::{self, EntityTag, ETag, HttpDate, IfMatch, IfModifiedSince, IfNoneMatch, LastModified}, web, HttpMessage, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use uuid::Uuid;
+
+
+/// Strong validator for a row, derived from its `updated_at` timestamp.
+pub fn entity_tag(updated_at: Option<DateTime<Utc>>) -> EntityTag {
+    let version = updated_at.map(|updated_at| updated_at.timestamp_micros()).unwrap_or(0);
+
+    EntityTag::new_strong(format!("{:x}", version))
+}
+
+/// Weak validator for a page of rows. Besides each row's version it covers the
+/// total count, so inserts and deletes outside the page also change the tag.
+pub fn collection_tag<I>(versions: I, count: i64) -> EntityTag
+where
+    I: IntoIterator<Item = (Uuid, Option<DateTime<Utc>>)>
+{
+    let mut hasher = DefaultHasher::new();
+    count.hash(&mut hasher);
+    for (id, updated_at) in versions {
+        id.hash(&mut hasher);
+        updated_at.map(|updated_at| updated_at.timestamp_micros()).hash(&mut hasher);
+    }
+
+    EntityTag::new_weak(format!("{:x}", hasher.finish()))
+}
+
+/// Evaluates an `If-Match` precondition against the current representation.
+/// Requests without the header always pass; note that the extractor yields an
+/// empty item list rather than `None` when the header is missing.
+pub fn if_match(header: &Option<web::Header<IfMatch>>, etag: &EntityTag) -> bool {
+    match header.as_deref() {
+        None | Some(IfMatch::Any) => true,
+        Some(IfMatch::Items(tags)) => tags.is_empty() || tags.iter().any(|tag| tag.strong_eq(etag))
+    }
+}
+
+/// Whether the client's cached copy is still current. `If-None-Match` takes
+/// precedence; `If-Modified-Since` is only consulted without it.
+pub fn is_not_modified(req: &HttpRequest, etag: &EntityTag, last_modified: Option<DateTime<Utc>>) -> bool {
+    if let Some(if_none_match) = req.get_header::<IfNoneMatch>() {
+        return match if_none_match {
+            IfNoneMatch::Any => true,
+            IfNoneMatch::Items(tags) => tags.iter().any(|tag| tag.weak_eq(etag))
+        };
+    }
+
+    match (req.get_header::<IfModifiedSince>(), last_modified) {
+        (Some(IfModifiedSince(since)), Some(last_modified)) => {
+            let since = DateTime::<Utc>::from(SystemTime::from(since));
+            // HTTP dates only carry whole seconds.
+            last_modified.timestamp() <= since.timestamp()
+        },
+        _ => false
+    }
+}
+
+/// Builds a cacheable GET response, answering `304 Not Modified` when the
+/// client's validators still match.
+pub fn conditional_response(req: &HttpRequest, etag: EntityTag, last_modified: Option<DateTime<Utc>>, cache_control: &str, body: Value) -> HttpResponse {
+    let not_modified = is_not_modified(req, &etag, last_modified);
+
+    let mut response = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response
+        .insert_header(ETag(etag))
+        .insert_header((header::CACHE_CONTROL, cache_control));
+    if let Some(last_modified) = last_modified {
+        response.insert_header(LastModified(HttpDate::from(SystemTime::from(last_modified))));
+    }
+
+    if not_modified {
+        return response.finish();
+    }
+    response.json(body)
+}
@@ -0,0 +1,74 @@
+use actix_web::{error::{self, JsonPayloadError}, http::StatusCode, web, HttpResponse};
+use serde_json::json;
+
+
+pub fn error_response(status: StatusCode, message: String) -> HttpResponse {
+    let json_error = json!({
+        "status": "error",
+        "message": message
+    });
+
+    HttpResponse::build(status).json(json_error)
+}
+
+/// JSON bodies: rejects payloads above `limit` bytes and reports malformed
+/// input with the standard `{status, message}` envelope.
+pub fn json_config(limit: usize) -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(limit)
+        .error_handler(|err, _req| {
+            let response = match &err {
+                JsonPayloadError::OverflowKnownLength { length, limit } => error_response(
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    format!("Request body is {} bytes, the limit is {} bytes", length, limit)
+                ),
+                JsonPayloadError::Overflow { limit } => error_response(
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    format!("Request body exceeds the limit of {} bytes", limit)
+                ),
+                JsonPayloadError::ContentType => error_response(
+                    StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                    "Content-Type must be a JSON media type such as application/json".to_string()
+                ),
+                JsonPayloadError::Deserialize(json_err) => error_response(
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid JSON body: {}", json_err)
+                ),
+                _ => error_response(
+                    StatusCode::BAD_REQUEST,
+                    format!("{}", err)
+                )
+            };
+
+            error::InternalError::from_response(err, response).into()
+        })
+}
+
+/// Raw payloads (`Bytes`, `String`) are capped at `limit` bytes.
+pub fn payload_config(limit: usize) -> web::PayloadConfig {
+    web::PayloadConfig::new(limit)
+}
+
+pub fn path_config() -> web::PathConfig {
+    web::PathConfig::default()
+        .error_handler(|err, req| {
+            let response = error_response(
+                StatusCode::BAD_REQUEST,
+                format!("Invalid path parameter in {}: {}", req.path(), err)
+            );
+
+            error::InternalError::from_response(err, response).into()
+        })
+}
+
+pub fn query_config() -> web::QueryConfig {
+    web::QueryConfig::default()
+        .error_handler(|err, req| {
+            let response = error_response(
+                StatusCode::BAD_REQUEST,
+                format!("Invalid query string '{}': {}", req.query_string(), err)
+            );
+
+            error::InternalError::from_response(err, response).into()
+        })
+}
@@ -0,0 +1,64 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use serde_json::json;
+
+use crate::{AppState, auth::Admin, models::audit::{AuditFilterOptions, AuditLogModel}};
+
+#[get("")]
+async fn get_audit_log(data: web::Data<AppState>, _admin: Admin, opts: web::Query<AuditFilterOptions>) -> impl Responder {
+    let limit = opts.limit.unwrap_or(10);
+    let offset = opts.offset.unwrap_or(1).saturating_sub(1) * limit;
+
+    let query_result = sqlx::query_as!(
+        AuditLogModel,
+        "SELECT * FROM audit_log WHERE resource_type = COALESCE($1, resource_type) AND resource_id = COALESCE($2, resource_id) AND actor = COALESCE($3, actor) ORDER BY created_at DESC LIMIT $4 OFFSET $5",
+        opts.resource,
+        opts.id,
+        opts.actor,
+        limit as i32,
+        offset as i32
+    )
+        .fetch_all(&data.db)
+        .await;
+
+    let entries = match query_result {
+        Ok(entries) => entries,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_count = sqlx::query_scalar("SELECT COUNT(*) FROM audit_log WHERE resource_type = COALESCE($1, resource_type) AND resource_id = COALESCE($2, resource_id) AND actor = COALESCE($3, actor)")
+        .bind(&opts.resource)
+        .bind(opts.id)
+        .bind(&opts.actor)
+        .fetch_one(&data.db)
+        .await;
+
+    let entry_count: i64 = match query_count {
+        Ok(count) => count,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+    let json_response = json!({
+        "status": "success",
+        "count": entry_count,
+        "data": entries
+    });
+
+    HttpResponse::Ok().json(json_response)
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    let scope = web::scope("/audit")
+        .service(get_audit_log);
+    cfg.service(scope);
+}
@@ -0,0 +1,582 @@
+use actix_web::{get, post, delete, web, Responder, HttpResponse};
+use chrono::Utc;
+use serde_json::json;
+use sqlx::types::BigDecimal;
+use uuid::Uuid;
+
+use crate::{AppState, audit::{self, AuditContext}, auth::CurrentUser, inventory, schema::PathOptions, models::{carts::{CartItemModel, CartLineModel, SetCartItem, CartItemPath, Checkout}, purchases::PurchaseModel}};
+
+#[get("")]
+async fn get_cart(data: web::Data<AppState>, user: CurrentUser, path: web::Path<PathOptions>) -> impl Responder {
+    let user_id = path.into_inner().id;
+
+    if !user.is(user_id) {
+        return not_your_cart(user_id);
+    }
+
+    // Lines are priced from the current product price; expired reservations
+    // are reported as unreserved even before the sweeper gets to them.
+    let query_result = sqlx::query_as!(
+        CartLineModel,
+        r#"SELECT c.product_id, p.product_name, c.quantity, p.price AS unit_price, p.price * c.quantity AS "total!",
+                CASE WHEN c.reserved_until > NOW() THEN c.reserved_until END AS reserved_until
+            FROM cart_items c JOIN products p ON p.id = c.product_id
+            WHERE c.user_id = $1 AND p.deleted_at IS NULL
+            ORDER BY c.created_at"#,
+        user_id
+    )
+        .fetch_all(&data.db)
+        .await;
+
+    let lines = match query_result {
+        Ok(lines) => lines,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let total = lines.iter().fold(BigDecimal::from(0), |total, line| total + &line.total);
+    let json_response = json!({
+        "status": "success",
+        "data": {
+            "user_id": user_id,
+            "currency": data.config.currency,
+            "total": total,
+            "count": lines.len(),
+            "items": lines
+        }
+    });
+
+    HttpResponse::Ok().json(json_response)
+}
+
+/// Adds a line or replaces its quantity. With reservations enabled the line
+/// takes its units out of stock until the reservation expires.
+#[post("/items")]
+async fn set_cart_item(data: web::Data<AppState>, audit: AuditContext, user: CurrentUser, path: web::Path<PathOptions>, body: web::Json<SetCartItem>) -> impl Responder {
+    let user_id = path.into_inner().id;
+
+    if !user.is(user_id) {
+        return not_your_cart(user_id);
+    }
+
+    if body.quantity < 1 {
+        let json_error = json!({
+            "status": "error",
+            "message": "quantity must be at least 1"
+        });
+        return HttpResponse::BadRequest().json(json_error);
+    }
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_scalar!(
+        "SELECT id FROM users WHERE id = $1 AND deleted_at IS NULL FOR SHARE",
+        user_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    match query_result {
+        Ok(Some(_)) => {},
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no user with ID: {}", user_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    // Cart rows are locked before products, the same order as checkout and
+    // the reservation sweeper.
+    let query_result = sqlx::query_as!(
+        CartItemModel,
+        "SELECT * FROM cart_items WHERE user_id = $1 AND product_id = $2 FOR UPDATE",
+        user_id,
+        body.product_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let existing = match query_result {
+        Ok(existing) => existing,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_scalar!(
+        "SELECT id FROM products WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+        body.product_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    match query_result {
+        Ok(Some(_)) => {},
+        Ok(None) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("There is no product with ID: {}", body.product_id)
+            });
+            return HttpResponse::UnprocessableEntity().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    let before = existing.as_ref().and_then(audit::snapshot);
+
+    if let Some(CartItemModel { warehouse_id: Some(warehouse_id), quantity, .. }) = existing {
+        let query_result = inventory::release(&mut tx, warehouse_id, body.product_id, quantity).await;
+        if let Err(err) = query_result {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    let now = Utc::now();
+    let (warehouse_id, reserved_until) = if data.config.cart_reservation_ttl_secs > 0 {
+        match inventory::fulfil(&mut tx, body.product_id, body.quantity, data.config.fulfilment_strategy, None).await {
+            Ok(Some(warehouse_id)) => (Some(warehouse_id), Some(now + chrono::Duration::seconds(data.config.cart_reservation_ttl_secs))),
+            Ok(None) => {
+                let available = inventory::on_hand(&mut tx, body.product_id).await.ok().flatten().unwrap_or(0);
+                let json_error = json!({
+                    "status": "error",
+                    "message": format!("Product {} is out of stock: requested {}, available {} across all warehouses", body.product_id, body.quantity, available)
+                });
+                return HttpResponse::Conflict().json(json_error);
+            },
+            Err(err) => {
+                let json_error = json!({
+                    "status": "error",
+                    "message": format!("{}", err)
+                });
+                return HttpResponse::InternalServerError().json(json_error);
+            }
+        }
+    } else {
+        (None, None)
+    };
+
+    let query_result = sqlx::query_as!(
+        CartItemModel,
+        "INSERT INTO cart_items (user_id, product_id, quantity, warehouse_id, reserved_until, updated_at) VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (user_id, product_id) DO UPDATE
+            SET quantity = EXCLUDED.quantity, warehouse_id = EXCLUDED.warehouse_id, reserved_until = EXCLUDED.reserved_until, updated_at = EXCLUDED.updated_at
+            RETURNING *",
+        user_id,
+        body.product_id,
+        body.quantity,
+        warehouse_id,
+        reserved_until,
+        now
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let item = match query_result {
+        Ok(item) => item,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let action = if before.is_some() { "update" } else { "create" };
+    let query_result = audit::record(&mut tx, &audit, action, "cart_items", item.id, before, audit::snapshot(&item)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": item
+    });
+    HttpResponse::Ok().json(json_response)
+}
+
+#[delete("/items/{product_id}")]
+async fn delete_cart_item(data: web::Data<AppState>, audit: AuditContext, user: CurrentUser, path: web::Path<CartItemPath>) -> impl Responder {
+    let CartItemPath { id: user_id, product_id } = path.into_inner();
+
+    if !user.is(user_id) {
+        return not_your_cart(user_id);
+    }
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        CartItemModel,
+        "DELETE FROM cart_items WHERE user_id = $1 AND product_id = $2 RETURNING *",
+        user_id,
+        product_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let item = match query_result {
+        Ok(Some(item)) => item,
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("Product {} is not in the cart of user {}", product_id, user_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    if let Some(warehouse_id) = item.warehouse_id {
+        let query_result = inventory::release(&mut tx, warehouse_id, product_id, item.quantity).await;
+        if let Err(err) = query_result {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    let query_result = audit::record(&mut tx, &audit, "delete", "cart_items", item.id, audit::snapshot(&item), None).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "message": format!("Product {} removed from the cart", product_id)
+    });
+
+    HttpResponse::Ok().json(json_response)
+}
+
+/// Turns every cart line into a purchase at the current price and empties
+/// the cart, all in one transaction. Lines with a live reservation are
+/// fulfilled from the reserved warehouse.
+#[post("/checkout")]
+async fn checkout(data: web::Data<AppState>, audit: AuditContext, user: CurrentUser, path: web::Path<PathOptions>, body: Option<web::Json<Checkout>>) -> impl Responder {
+    let user_id = path.into_inner().id;
+
+    if !user.is(user_id) {
+        return not_your_cart(user_id);
+    }
+    let body = body.map(|body| body.into_inner()).unwrap_or_default();
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_scalar!(
+        "SELECT id FROM users WHERE id = $1 AND deleted_at IS NULL FOR SHARE",
+        user_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    match query_result {
+        Ok(Some(_)) => {},
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no user with ID: {}", user_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    let query_result = sqlx::query_as!(
+        CartItemModel,
+        "SELECT * FROM cart_items WHERE user_id = $1 ORDER BY product_id FOR UPDATE",
+        user_id
+    )
+        .fetch_all(&mut tx)
+        .await;
+
+    let items = match query_result {
+        Ok(items) => items,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+    if items.is_empty() {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("The cart of user {} is empty", user_id)
+        });
+        return HttpResponse::UnprocessableEntity().json(json_error);
+    }
+    let product_ids: Vec<Uuid> = items.iter().map(|item| item.product_id).collect();
+
+    // Products are locked in id order, matching order creation.
+    let query_result = sqlx::query!(
+        "SELECT id, price, stock_quantity FROM products WHERE id = ANY($1) AND deleted_at IS NULL ORDER BY id FOR UPDATE",
+        &product_ids
+    )
+        .fetch_all(&mut tx)
+        .await;
+
+    let products = match query_result {
+        Ok(products) => products,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let missing: Vec<Uuid> = product_ids
+        .iter()
+        .filter(|product_id| !products.iter().any(|product| product.id == **product_id))
+        .copied()
+        .collect();
+    if !missing.is_empty() {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{} products in the cart no longer exist", missing.len()),
+            "missing_products": missing
+        });
+        return HttpResponse::UnprocessableEntity().json(json_error);
+    }
+
+    let now = Utc::now();
+    let mut quantities = Vec::with_capacity(items.len());
+    let mut unit_prices = Vec::with_capacity(items.len());
+    let mut warehouse_ids = Vec::with_capacity(items.len());
+    let mut out_of_stock = Vec::new();
+    for item in &items {
+        let reserved = match (item.warehouse_id, item.reserved_until) {
+            (Some(warehouse_id), Some(reserved_until)) if reserved_until > now => Some(warehouse_id),
+            (Some(warehouse_id), _) => {
+                let query_result = inventory::release(&mut tx, warehouse_id, item.product_id, item.quantity).await;
+                if let Err(err) = query_result {
+                    let json_error = json!({
+                        "status": "error",
+                        "message": format!("{}", err)
+                    });
+                    return HttpResponse::InternalServerError().json(json_error);
+                }
+                None
+            },
+            _ => None
+        };
+
+        let warehouse_id = match reserved {
+            Some(warehouse_id) => warehouse_id,
+            None => match inventory::fulfil(&mut tx, item.product_id, item.quantity, data.config.fulfilment_strategy, body.ship_to.as_ref()).await {
+                Ok(Some(warehouse_id)) => warehouse_id,
+                Ok(None) => {
+                    let available = inventory::on_hand(&mut tx, item.product_id).await.ok().flatten().unwrap_or(0);
+                    out_of_stock.push(json!({
+                        "product_id": item.product_id,
+                        "requested": item.quantity,
+                        "available": available
+                    }));
+                    continue;
+                },
+                Err(err) => {
+                    let json_error = json!({
+                        "status": "error",
+                        "message": format!("{}", err)
+                    });
+                    return HttpResponse::InternalServerError().json(json_error);
+                }
+            }
+        };
+
+        let price = products
+            .iter()
+            .find(|product| product.id == item.product_id)
+            .map(|product| product.price.clone())
+            .unwrap_or_default();
+        quantities.push(item.quantity);
+        unit_prices.push(price);
+        warehouse_ids.push(warehouse_id);
+    }
+    if !out_of_stock.is_empty() {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{} products in the cart are out of stock", out_of_stock.len()),
+            "out_of_stock": out_of_stock
+        });
+        return HttpResponse::Conflict().json(json_error);
+    }
+
+    let query_result = sqlx::query_as!(
+        PurchaseModel,
+        "INSERT INTO purchases (user_id, currency, product_id, quantity, unit_price, warehouse_id)
+            SELECT $1, $2, * FROM UNNEST($3::uuid[], $4::int[], $5::numeric[], $6::uuid[])
+            RETURNING *",
+        user_id,
+        data.config.currency,
+        &product_ids,
+        &quantities,
+        &unit_prices,
+        &warehouse_ids
+    )
+        .fetch_all(&mut tx)
+        .await;
+
+    let purchases = match query_result {
+        Ok(purchases) => purchases,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    for purchase in &purchases {
+        let query_result = audit::record(&mut tx, &audit, "create", "purchases", purchase.id, None, audit::snapshot(purchase)).await;
+        if let Err(err) = query_result {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    let query_result = sqlx::query!("DELETE FROM cart_items WHERE user_id = $1", user_id)
+        .execute(&mut tx)
+        .await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "count": purchases.len(),
+        "data": purchases
+    });
+    HttpResponse::Ok().json(json_response)
+}
+
+/// 403 for a caller touching someone else's cart.
+fn not_your_cart(user_id: Uuid) -> HttpResponse {
+    let json_error = json!({
+        "status": "error",
+        "message": format!("Only user {} can use their cart", user_id)
+    });
+    HttpResponse::Forbidden().json(json_error)
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    // Registered ahead of `/users` so these paths are not swallowed by that scope.
+    let scope = web::scope("/users/{id}/cart")
+        .service(get_cart)
+        .service(set_cart_item)
+        .service(delete_cart_item)
+        .service(checkout);
+
+    cfg.service(scope);
+}
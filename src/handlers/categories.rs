@@ -1,127 +1,473 @@
-use actix_web::{get, post, patch, delete, web, Responder, HttpResponse};
+use actix_web::{get, post, patch, delete, web, Responder, HttpResponse, HttpRequest, http::header::{ETag, IfMatch}};
 use serde_json::json;
+use json_patch::Patch as JsonPatch;
 use chrono::Utc;
 
-use crate::{AppState, schema::{FilterOptions, PathOptions}, models::categories::{CategoryModel, CreateCategory, UpdateCategory}};
+use crate::{AppState, handlers::ratings, audit::{self, AuditContext}, auth::Admin, etag, hierarchy, inventory, patching, schema::{DeleteOptions, DeleteStrategy, DeletedOptions, FilterOptions, PathOptions}, models::categories::{CategoryModel, CreateCategory, UpdateCategory}};
 
 #[get("")]
-async fn get_categories(data: web::Data<AppState>, opts: web::Query<FilterOptions>) -> impl Responder {
+async fn get_categories(req: HttpRequest, data: web::Data<AppState>, admin: Option<Admin>, opts: web::Query<FilterOptions>) -> impl Responder {
     let limit = opts.limit.unwrap_or(10);
-    let offset = (opts.offset.unwrap_or(1) - 1) * 1;
+    let offset = opts.offset.unwrap_or(1).saturating_sub(1) * limit;
+
+    let include_deleted = opts.include_deleted.unwrap_or(false);
+    if include_deleted && admin.is_none() {
+        let json_error = json!({
+            "status": "error",
+            "message": "include_deleted requires admin credentials"
+        });
+        return HttpResponse::Forbidden().json(json_error);
+    }
 
     let query_result = sqlx::query_as!(
         CategoryModel,
-        "SELECT * FROM categories ORDER BY created_at DESC LIMIT $1 OFFSET $2",
+        "SELECT * FROM categories WHERE (deleted_at IS NULL OR $1) ORDER BY created_at DESC LIMIT $2 OFFSET $3",
+        include_deleted,
         limit as i32,
         offset as i32
     )
         .fetch_all(&data.db)
         .await;
 
-    match query_result {
-        Ok(categories) => {
-            let query_count = sqlx::query_scalar("SELECT COUNT(*) FROM categories").fetch_one(&data.db).await;
+    let categories = match query_result {
+        Ok(categories) => categories,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
 
-            let categories_count: i64 = match query_count {
-                Ok(count) => count,
-                Err(err) => {
-                   let json_error = json!({
-                        "status": "error",
-                        "message": format!("{}", err)
-                    }); 
-                    return HttpResponse::InternalServerError().json(json_error)
-                }
-            };
-            let json_response = json!({
-                "status": "success",
-                "count": categories_count,
-                "data": categories
+    let query_count = sqlx::query_scalar("SELECT COUNT(*) FROM categories WHERE (deleted_at IS NULL OR $1)")
+        .bind(include_deleted)
+        .fetch_one(&data.db)
+        .await;
+
+    let categories_count: i64 = match query_count {
+        Ok(count) => count,
+        Err(err) => {
+           let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            }); 
+            return HttpResponse::InternalServerError().json(json_error)
+        }
+    };
+    let etag = etag::collection_tag(categories.iter().map(|category| (category.id, category.updated_at)), categories_count);
+    let json_response = json!({
+        "status": "success",
+        "count": categories_count,
+        "data": categories
+    });
+    etag::conditional_response(&req, etag, None, &data.config.categories_cache_control, json_response)
+}
+
+#[post("")]
+async fn create_category(data: web::Data<AppState>, audit: AuditContext, body: web::Json<CreateCategory>) -> impl Responder {
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
             });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
 
-            return HttpResponse::Ok().json(json_response)
-        },
+    if let Some(parent_id) = body.parent_id {
+        let query_result = sqlx::query_scalar!(
+            "SELECT id FROM categories WHERE id = $1 AND deleted_at IS NULL FOR SHARE",
+            parent_id
+        )
+            .fetch_optional(&mut tx)
+            .await;
+
+        match query_result {
+            Ok(Some(_)) => {},
+            Ok(None) => {
+                let json_error = json!({
+                    "status": "error",
+                    "message": format!("There is no category with ID: {}", parent_id)
+                });
+                return HttpResponse::UnprocessableEntity().json(json_error);
+            },
+            Err(err) => {
+                let json_error = json!({
+                    "status": "error",
+                    "message": format!("{}", err)
+                });
+                return HttpResponse::InternalServerError().json(json_error);
+            }
+        }
+    }
+
+    let query_result = sqlx::query_as!(
+        CategoryModel,
+        "INSERT INTO categories (category_name, parent_id) VALUES ($1, $2) RETURNING *",
+        body.category_name.to_string(),
+        body.parent_id
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let category = match query_result {
+        Ok(category) => category,
         Err(err) => {
             let json_error = json!({
                 "status": "error",
                 "message": format!("{}", err)
             });
-            return HttpResponse::InternalServerError().json(json_error)
+            return HttpResponse::InternalServerError().json(json_error);
         }
+    };
+    let category_id = category.id;
+
+    let query_result = audit::record(&mut tx, &audit, "create", "categories", category_id, None, audit::snapshot(&category)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
     }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": category
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(category.updated_at))).json(json_response)
 }
 
-#[post("")]
-async fn create_category(data: web::Data<AppState>, body: web::Json<CreateCategory>) -> impl Responder {
+#[get("/{id}")]
+async fn get_category(req: HttpRequest, data: web::Data<AppState>, admin: Option<Admin>, path: web::Path<PathOptions>, opts: web::Query<DeletedOptions>) -> impl Responder {
+    let category_id = path.into_inner().id;
+
+    let include_deleted = opts.include_deleted.unwrap_or(false);
+    if include_deleted && admin.is_none() {
+        let json_error = json!({
+            "status": "error",
+            "message": "include_deleted requires admin credentials"
+        });
+        return HttpResponse::Forbidden().json(json_error);
+    }
+
     let query_result = sqlx::query_as!(
         CategoryModel,
-        "INSERT INTO categories (category_name) VALUES ($1) RETURNING *",
-        body.category_name.to_string()
+        "SELECT * FROM categories WHERE id = $1 AND (deleted_at IS NULL OR $2)",
+        category_id,
+        include_deleted
     )
         .fetch_one(&data.db)
         .await;
 
-    match query_result {
-        Ok(category) => {
-            let json_response = json!({
-                "status": "success",
-                "data": category
+    let category = match query_result {
+        Ok(category) => category,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
             });
 
-            return HttpResponse::Ok().json(json_response);
-        },
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let etag = etag::entity_tag(category.updated_at);
+    let last_modified = category.updated_at;
+    let json_response = json!({
+        "status": "succcess",
+        "data": category
+    });
+    etag::conditional_response(&req, etag, last_modified, &data.config.categories_cache_control, json_response)
+}
+
+
+/// Every live category nested under its parent.
+#[get("/tree")]
+async fn get_category_tree(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    let query_result = sqlx::query_as!(
+        CategoryModel,
+        "SELECT * FROM categories WHERE deleted_at IS NULL ORDER BY category_name"
+    )
+        .fetch_all(&data.db)
+        .await;
+
+    let categories = match query_result {
+        Ok(categories) => categories,
         Err(err) => {
             let json_error = json!({
                 "status": "error",
                 "message": format!("{}", err)
             });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let count = categories.len() as i64;
+    let etag = etag::collection_tag(categories.iter().map(|category| (category.id, category.updated_at)), count);
+    let json_response = json!({
+        "status": "success",
+        "count": count,
+        "data": hierarchy::build_tree(categories)
+    });
+    etag::conditional_response(&req, etag, None, &data.config.categories_cache_control, json_response)
+}
+
+/// All live categories below a category, at any depth.
+#[get("/{id}/descendants")]
+async fn get_category_descendants(data: web::Data<AppState>, path: web::Path<PathOptions>) -> impl Responder {
+    let category_id = path.into_inner().id;
+
+    let query_result = sqlx::query_scalar!(
+        "SELECT id FROM categories WHERE id = $1 AND deleted_at IS NULL",
+        category_id
+    )
+        .fetch_optional(&data.db)
+        .await;
 
+    match query_result {
+        Ok(Some(_)) => {},
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no category with ID: {}", category_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
             return HttpResponse::InternalServerError().json(json_error);
         }
     }
+
+    let query_result = sqlx::query_as!(
+        CategoryModel,
+        r#"WITH RECURSIVE subtree(id, depth) AS (
+                SELECT id, 1 FROM categories WHERE parent_id = $1 AND deleted_at IS NULL
+                UNION
+                SELECT c.id, s.depth + 1 FROM categories c JOIN subtree s ON c.parent_id = s.id WHERE c.deleted_at IS NULL AND s.depth < 100
+            )
+            SELECT c.* FROM categories c JOIN subtree s ON s.id = c.id
+            ORDER BY s.depth, c.category_name"#,
+        category_id
+    )
+        .fetch_all(&data.db)
+        .await;
+
+    let categories = match query_result {
+        Ok(categories) => categories,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let json_response = json!({
+        "status": "success",
+        "count": categories.len(),
+        "data": categories
+    });
+    HttpResponse::Ok().json(json_response)
 }
 
-#[get("/{id}")]
-async fn get_category(data: web::Data<AppState>, path: web::Path<PathOptions>) -> impl Responder {
+/// The path from the root down to a category, the category itself last. A
+/// soft-deleted ancestor cuts the path off below it.
+#[get("/{id}/breadcrumbs")]
+async fn get_category_breadcrumbs(data: web::Data<AppState>, path: web::Path<PathOptions>) -> impl Responder {
     let category_id = path.into_inner().id;
 
     let query_result = sqlx::query_as!(
         CategoryModel,
-        "SELECT * FROM categories WHERE id = $1",
+        r#"WITH RECURSIVE ancestors(id, parent_id, depth) AS (
+                SELECT id, parent_id, 0 FROM categories WHERE id = $1 AND deleted_at IS NULL
+                UNION
+                SELECT c.id, c.parent_id, a.depth + 1 FROM categories c JOIN ancestors a ON c.id = a.parent_id WHERE c.deleted_at IS NULL AND a.depth < 100
+            )
+            SELECT c.* FROM categories c JOIN ancestors a ON a.id = c.id
+            ORDER BY a.depth DESC"#,
         category_id
     )
-        .fetch_one(&data.db)
+        .fetch_all(&data.db)
         .await;
 
-    match query_result {
-        Ok(category) => {
-            let json_response = json!({
-                "status": "succcess",
-                "data": category
+    let categories = match query_result {
+        Ok(categories) if categories.is_empty() => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no category with ID: {}", category_id)
             });
-            return HttpResponse::Ok().json(json_response);
+            return HttpResponse::NotFound().json(json_error);
         },
+        Ok(categories) => categories,
         Err(err) => {
             let json_error = json!({
                 "status": "error",
                 "message": format!("{}", err)
             });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let json_response = json!({
+        "status": "success",
+        "data": categories
+    });
+    HttpResponse::Ok().json(json_response)
+}
 
+#[patch("/{id}", guard = "patching::is_json_patch")]
+async fn json_patch_category(data: web::Data<AppState>, audit: AuditContext, path: web::Path<PathOptions>, if_match: Option<web::Header<IfMatch>>, body: web::Json<JsonPatch>) -> impl Responder {
+    let category_id = path.into_inner().id;
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        CategoryModel,
+        "SELECT * FROM categories WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+        category_id
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let category = match query_result {
+        Ok(category) => category,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
             return HttpResponse::InternalServerError().json(json_error);
         }
+    };
+    let before = audit::snapshot(&category);
+
+    if !etag::if_match(&if_match, &etag::entity_tag(category.updated_at)) {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("Category {} has been modified since it was fetched", category_id)
+        });
+        return HttpResponse::PreconditionFailed().json(json_error);
     }
-}
 
+    let previous_parent_id = category.parent_id;
+
+    let category: CategoryModel = match patching::apply(&category, &body, &patching::IMMUTABLE_FIELDS) {
+        Ok(category) => category,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::build(err.status()).json(json_error);
+        }
+    };
+
+    // Moving a category must not put it below itself.
+    if let Some(parent_id) = category.parent_id.filter(|parent_id| Some(*parent_id) != previous_parent_id) {
+        if let Err((status, message)) = hierarchy::validate_parent(&mut tx, category_id, parent_id).await {
+            let json_error = json!({
+                "status": "error",
+                "message": message
+            });
+            return HttpResponse::build(status).json(json_error);
+        }
+    }
+
+    let now = Utc::now();
+
+    let query_result = sqlx::query_as!(
+        CategoryModel,
+        "UPDATE categories SET category_name = $1, parent_id = $2, updated_at = $3 WHERE id = $4 RETURNING *",
+        category.category_name,
+        category.parent_id,
+        now,
+        category_id
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let category = match query_result {
+        Ok(category) => category,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = audit::record(&mut tx, &audit, "update", "categories", category_id, before, audit::snapshot(&category)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": category
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(category.updated_at))).json(json_response)
+}
 
 #[patch("/{id}")]
-async fn update_category(data: web::Data<AppState>, path: web::Path<PathOptions>, body: web::Json<UpdateCategory>) -> impl Responder {
+async fn update_category(data: web::Data<AppState>, audit: AuditContext, path: web::Path<PathOptions>, if_match: Option<web::Header<IfMatch>>, body: web::Json<UpdateCategory>) -> impl Responder {
     let category_id = path.into_inner().id;
 
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
     let query_result = sqlx::query_as!(
         CategoryModel,
-        "SELECT * FROM categories WHERE id = $1",
+        "SELECT * FROM categories WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
         category_id
     )
-        .fetch_one(&data.db)
+        .fetch_one(&mut tx)
         .await;
 
     let category = match query_result {
@@ -131,32 +477,58 @@ async fn update_category(data: web::Data<AppState>, path: web::Path<PathOptions>
                 "status": "error",
                 "message": format!("{}", err)
             });
-
             return HttpResponse::InternalServerError().json(json_error);
         }
     };
+    let before = audit::snapshot(&category);
+
+    if !etag::if_match(&if_match, &etag::entity_tag(category.updated_at)) {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("Category {} has been modified since it was fetched", category_id)
+        });
+        return HttpResponse::PreconditionFailed().json(json_error);
+    }
+
+    let previous_parent_id = category.parent_id;
+
+    let category = match body.into_inner().merge_into(category) {
+        Ok(category) => category,
+        Err(message) => {
+            let json_error = json!({
+                "status": "error",
+                "message": message
+            });
+            return HttpResponse::BadRequest().json(json_error);
+        }
+    };
+
+    // Moving a category must not put it below itself.
+    if let Some(parent_id) = category.parent_id.filter(|parent_id| Some(*parent_id) != previous_parent_id) {
+        if let Err((status, message)) = hierarchy::validate_parent(&mut tx, category_id, parent_id).await {
+            let json_error = json!({
+                "status": "error",
+                "message": message
+            });
+            return HttpResponse::build(status).json(json_error);
+        }
+    }
 
     let now = Utc::now();
 
     let query_result = sqlx::query_as!(
         CategoryModel,
-        "UPDATE categories SET category_name = $1, updated_at = $2 WHERE id = $3 RETURNING *",
-        body.category_name.to_owned().unwrap_or(category.category_name),
+        "UPDATE categories SET category_name = $1, parent_id = $2, updated_at = $3 WHERE id = $4 RETURNING *",
+        category.category_name,
+        category.parent_id,
         now,
         category_id
     )
-        .fetch_one(&data.db)
+        .fetch_one(&mut tx)
         .await;
 
-    match query_result {
-        Ok(category) => {
-            let json_response = json!({
-                "status": "success",
-                "data": category
-            });
-
-            return HttpResponse::Ok().json(json_response);
-        },
+    let category = match query_result {
+        Ok(category) => category,
         Err(err) => {
             let json_error = json!({
                 "status": "error",
@@ -164,21 +536,301 @@ async fn update_category(data: web::Data<AppState>, path: web::Path<PathOptions>
             });
             return HttpResponse::InternalServerError().json(json_error);
         }
+    };
+
+    let query_result = audit::record(&mut tx, &audit, "update", "categories", category_id, before, audit::snapshot(&category)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
     }
+
+    let json_response = json!({
+        "status": "success",
+        "data": category
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(category.updated_at))).json(json_response)
 }
 
 
 #[delete("/{id}")]
-async fn delete_category(data: web::Data<AppState>, path: web::Path<PathOptions>) -> impl Responder {
+async fn delete_category(data: web::Data<AppState>, audit: AuditContext, path: web::Path<PathOptions>, if_match: Option<web::Header<IfMatch>>, opts: web::Query<DeleteOptions>) -> impl Responder {
     let category_id = path.into_inner().id;
 
-    let query_result = sqlx::query_scalar!("DELETE FROM categories WHERE id = $1", category_id)
-        .execute(&data.db)
-        .await
-        .unwrap()
-        .rows_affected();
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        CategoryModel,
+        "SELECT * FROM categories WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+        category_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let category = match query_result {
+        Ok(category) => category,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    if let Some(category) = &category {
+        if !etag::if_match(&if_match, &etag::entity_tag(category.updated_at)) {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("Category {} has been modified since it was fetched", category_id)
+            });
+            return HttpResponse::PreconditionFailed().json(json_error);
+        }
+    }
+
+    let now = Utc::now();
+
+    if category.is_some() {
+        match opts.strategy() {
+            DeleteStrategy::Restrict => {
+                let query_result = sqlx::query_scalar!(
+                    "SELECT id FROM products WHERE category_id = $1 AND deleted_at IS NULL",
+                    category_id
+                )
+                    .fetch_all(&mut tx)
+                    .await;
+
+                let products = match query_result {
+                    Ok(ids) => ids,
+                    Err(err) => {
+                        let json_error = json!({
+                            "status": "error",
+                            "message": format!("{}", err)
+                        });
+                        return HttpResponse::InternalServerError().json(json_error);
+                    }
+                };
+
+                if !products.is_empty() {
+                    let json_error = json!({
+                        "status": "error",
+                        "message": format!("Category {} still has {} products", category_id, products.len()),
+                        "dependents": {
+                            "products": products
+                        }
+                    });
+                    return HttpResponse::Conflict().json(json_error);
+                }
+            },
+            DeleteStrategy::Reassign => {
+                let target_id = match opts.reassign_to {
+                    Some(target_id) if target_id != category_id => target_id,
+                    _ => {
+                        let json_error = json!({
+                            "status": "error",
+                            "message": "reassign_to must name another category"
+                        });
+                        return HttpResponse::BadRequest().json(json_error);
+                    }
+                };
+
+                let query_result = sqlx::query_scalar!(
+                    "SELECT id FROM categories WHERE id = $1 AND deleted_at IS NULL FOR SHARE",
+                    target_id
+                )
+                    .fetch_optional(&mut tx)
+                    .await;
+
+                match query_result {
+                    Ok(Some(_)) => {},
+                    Ok(None) => {
+                        let json_error = json!({
+                            "status": "error",
+                            "message": format!("There is no category with ID: {}", target_id)
+                        });
+                        return HttpResponse::UnprocessableEntity().json(json_error);
+                    },
+                    Err(err) => {
+                        let json_error = json!({
+                            "status": "error",
+                            "message": format!("{}", err)
+                        });
+                        return HttpResponse::InternalServerError().json(json_error);
+                    }
+                }
+
+                let query_result = sqlx::query!(
+                    "WITH affected AS (\n                        UPDATE products SET category_id = $1, updated_at = $2 FROM products old\n                        WHERE products.id = old.id AND products.category_id = $3\n                        RETURNING products.id, to_jsonb(old) AS before, to_jsonb(products) AS after\n                    )\n                    INSERT INTO audit_log (actor, action, resource_type, resource_id, before, after, request_id)\n                    SELECT $4, 'update', 'products', id, before, after, $5 FROM affected",
+                    target_id,
+                    now,
+                    category_id,
+                    audit.actor,
+                    audit.request_id
+                )
+                    .execute(&mut tx)
+                    .await;
+
+                if let Err(err) = query_result {
+                    let json_error = json!({
+                        "status": "error",
+                        "message": format!("{}", err)
+                    });
+                    return HttpResponse::InternalServerError().json(json_error);
+                }
+            },
+            DeleteStrategy::Cascade => {
+                let query_result = sqlx::query!(
+                    "WITH affected AS (\n                        UPDATE ratings SET deleted_at = $1, updated_at = $1 FROM ratings old\n                        WHERE ratings.id = old.id AND ratings.deleted_at IS NULL AND ratings.product_id IN (SELECT id FROM products WHERE category_id = $2 AND deleted_at IS NULL)\n                        RETURNING ratings.id, to_jsonb(old) AS before, to_jsonb(ratings) AS after\n                    )\n                    INSERT INTO audit_log (actor, action, resource_type, resource_id, before, after, request_id)\n                    SELECT $3, 'delete', 'ratings', id, before, after, $4 FROM affected",
+                    now,
+                    category_id,
+                    audit.actor,
+                    audit.request_id
+                )
+                    .execute(&mut tx)
+                    .await;
+
+                if let Err(err) = query_result {
+                    let json_error = json!({
+                        "status": "error",
+                        "message": format!("{}", err)
+                    });
+                    return HttpResponse::InternalServerError().json(json_error);
+                }
+
+                let query_result = sqlx::query!(
+                    r#"WITH affected AS (
+                            UPDATE purchases SET deleted_at = $1, updated_at = $1 FROM purchases old
+                            WHERE purchases.id = old.id AND purchases.deleted_at IS NULL AND purchases.product_id IN (SELECT id FROM products WHERE category_id = $2 AND deleted_at IS NULL)
+                            RETURNING purchases.*, to_jsonb(old) AS before, to_jsonb(purchases) AS after
+                        ), audited AS (
+                            INSERT INTO audit_log (actor, action, resource_type, resource_id, before, after, request_id)
+                            SELECT $3, 'delete', 'purchases', id, before, after, $4 FROM affected
+                        )
+                        SELECT product_id AS "product_id!", warehouse_id AS "warehouse_id!", quantity AS "quantity!", status AS "status!" FROM affected"#,
+                    now,
+                    category_id,
+                    audit.actor,
+                    audit.request_id
+                )
+                    .fetch_all(&mut tx)
+                    .await;
+
+                let deleted = match query_result {
+                    Ok(deleted) => deleted,
+                    Err(err) => {
+                        let json_error = json!({
+                            "status": "error",
+                            "message": format!("{}", err)
+                        });
+                        return HttpResponse::InternalServerError().json(json_error);
+                    }
+                };
+
+                // Deleted purchases no longer hold stock.
+                for purchase in deleted {
+                    if let Err(err) = inventory::release_purchase(&mut tx, &purchase.status, purchase.warehouse_id, purchase.product_id, purchase.quantity).await {
+                        let json_error = json!({
+                            "status": "error",
+                            "message": format!("{}", err)
+                        });
+                        return HttpResponse::InternalServerError().json(json_error);
+                    }
+                }
+
+                let query_result = sqlx::query!(
+                    "WITH affected AS (\n                        UPDATE products SET deleted_at = $1, updated_at = $1 FROM products old\n                        WHERE products.id = old.id AND products.category_id = $2 AND products.deleted_at IS NULL\n                        RETURNING products.id, to_jsonb(old) AS before, to_jsonb(products) AS after\n                    )\n                    INSERT INTO audit_log (actor, action, resource_type, resource_id, before, after, request_id)\n                    SELECT $3, 'delete', 'products', id, before, after, $4 FROM affected",
+                    now,
+                    category_id,
+                    audit.actor,
+                    audit.request_id
+                )
+                    .execute(&mut tx)
+                    .await;
+
+                if let Err(err) = query_result {
+                    let json_error = json!({
+                        "status": "error",
+                        "message": format!("{}", err)
+                    });
+                    return HttpResponse::InternalServerError().json(json_error);
+                }
+            }
+        }
+    }
+
+    // Subcategories move up to the deleted category's parent.
+    let query_result = sqlx::query!(
+        r#"UPDATE categories SET parent_id = $3, updated_at = $1 FROM categories old
+            WHERE categories.id = old.id AND categories.parent_id = $2 AND categories.deleted_at IS NULL
+            RETURNING categories.id, to_jsonb(old) AS before, to_jsonb(categories) AS after"#,
+        now,
+        category_id,
+        category.as_ref().and_then(|category| category.parent_id)
+    )
+        .fetch_all(&mut tx)
+        .await;
+
+    let moved = match query_result {
+        Ok(moved) => moved,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    for child in moved {
+        let query_result = audit::record(&mut tx, &audit, "update", "categories", child.id, child.before, child.after).await;
+        if let Err(err) = query_result {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    let query_result = sqlx::query!(
+        "UPDATE categories SET deleted_at = $1, updated_at = $1 WHERE id = $2 AND deleted_at IS NULL",
+        now,
+        category_id
+    )
+        .execute(&mut tx)
+        .await;
+
+    let rows_affected = match query_result {
+        Ok(result) => result.rows_affected(),
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
 
-    if query_result == 0 {
+    if rows_affected == 0 {
         let json_error = json!({
             "status": "not found",
             "message": format!("There is no category with ID: {}", category_id)
@@ -187,6 +839,23 @@ async fn delete_category(data: web::Data<AppState>, path: web::Path<PathOptions>
         return HttpResponse::NotFound().json(json_error);
     }
 
+    let query_result = audit::record(&mut tx, &audit, "delete", "categories", category_id, category.as_ref().and_then(audit::snapshot), None).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
     let json_response = json!({
         "status": "success",
         "message": format!("Category removed with ID: {}", category_id)
@@ -195,12 +864,161 @@ async fn delete_category(data: web::Data<AppState>, path: web::Path<PathOptions>
     HttpResponse::Ok().json(json_response)
 }
 
+#[get("/trash")]
+async fn get_deleted_categories(data: web::Data<AppState>, _admin: Admin, opts: web::Query<FilterOptions>) -> impl Responder {
+    let limit = opts.limit.unwrap_or(10);
+    let offset = opts.offset.unwrap_or(1).saturating_sub(1) * limit;
+
+    let query_result = sqlx::query_as!(
+        CategoryModel,
+        "SELECT * FROM categories WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC LIMIT $1 OFFSET $2",
+        limit as i32,
+        offset as i32
+    )
+        .fetch_all(&data.db)
+        .await;
+
+    let categories = match query_result {
+        Ok(categories) => categories,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_count = sqlx::query_scalar("SELECT COUNT(*) FROM categories WHERE deleted_at IS NOT NULL")
+        .fetch_one(&data.db)
+        .await;
+
+    let deleted_count: i64 = match query_count {
+        Ok(count) => count,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+    let json_response = json!({
+        "status": "success",
+        "count": deleted_count,
+        "data": categories
+    });
+
+    HttpResponse::Ok().json(json_response)
+}
+
+#[post("/{id}/restore")]
+async fn restore_category(data: web::Data<AppState>, _admin: Admin, audit: AuditContext, path: web::Path<PathOptions>) -> impl Responder {
+    let category_id = path.into_inner().id;
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        CategoryModel,
+        "SELECT * FROM categories WHERE id = $1 AND deleted_at IS NOT NULL FOR UPDATE",
+        category_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let before = match query_result {
+        Ok(Some(category)) => audit::snapshot(&category),
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no deleted category with ID: {}", category_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let now = Utc::now();
+
+    let query_result = sqlx::query_as!(
+        CategoryModel,
+        "UPDATE categories SET deleted_at = NULL, updated_at = $1 WHERE id = $2 RETURNING *",
+        now,
+        category_id
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let category = match query_result {
+        Ok(category) => category,
+        Err(sqlx::Error::Database(err)) if err.code().as_deref() == Some("23505") => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("Cannot restore category {}: {}", category_id, err)
+            });
+            return HttpResponse::Conflict().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = audit::record(&mut tx, &audit, "restore", "categories", category_id, before, audit::snapshot(&category)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": category
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(category.updated_at))).json(json_response)
+}
+
 pub fn config(cfg: &mut web::ServiceConfig) {
     let scope = web::scope("/categories")
         .service(get_categories)
+        .service(get_deleted_categories)
+        .service(get_category_tree)
         .service(create_category)
         .service(get_category)
+        .service(get_category_descendants)
+        .service(get_category_breadcrumbs)
+        .service(json_patch_category)
         .service(update_category)
-        .service(delete_category);
+        .service(delete_category)
+        .service(restore_category)
+        .service(ratings::get_category_rating_summary);
     cfg.service(scope);
 }
\ No newline at end of file
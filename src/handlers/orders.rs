@@ -0,0 +1,311 @@
+use actix_web::{get, post, web, HttpResponse, Responder};
+use serde_json::json;
+use sqlx::{types::BigDecimal, PgExecutor};
+use uuid::Uuid;
+
+use crate::{AppState, audit::{self, AuditContext}, inventory, models::orders::{CreateOrder, OrderItemModel, OrderModel, OrderWithItems}, schema::PathOptions};
+
+/// Attaches the lines of every order in `orders`, keeping their order.
+pub async fn with_items<'e, E>(executor: E, orders: Vec<OrderModel>) -> Result<Vec<OrderWithItems>, sqlx::Error>
+where
+    E: PgExecutor<'e>
+{
+    let order_ids: Vec<Uuid> = orders.iter().map(|order| order.id).collect();
+
+    let mut items = sqlx::query_as!(
+        OrderItemModel,
+        "SELECT * FROM order_items WHERE order_id = ANY($1) ORDER BY created_at, id",
+        &order_ids
+    )
+        .fetch_all(executor)
+        .await?;
+
+    let orders = orders
+        .into_iter()
+        .map(|order| {
+            let (own, rest): (Vec<_>, Vec<_>) = items.drain(..).partition(|item| item.order_id == order.id);
+            items = rest;
+            OrderWithItems { order, items: own }
+        })
+        .collect();
+
+    Ok(orders)
+}
+
+#[post("")]
+async fn create_order(data: web::Data<AppState>, audit: AuditContext, body: web::Json<CreateOrder>) -> impl Responder {
+    let body = body.into_inner();
+
+    if body.items.is_empty() {
+        let json_error = json!({
+            "status": "error",
+            "message": "An order needs at least one item"
+        });
+        return HttpResponse::BadRequest().json(json_error);
+    }
+
+    // Repeated products are folded into a single line.
+    let mut lines: Vec<(Uuid, i32)> = Vec::new();
+    for item in &body.items {
+        if item.quantity < 1 {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("quantity for product {} must be at least 1", item.product_id)
+            });
+            return HttpResponse::BadRequest().json(json_error);
+        }
+        match lines.iter_mut().find(|(product_id, _)| *product_id == item.product_id) {
+            Some(line) => line.1 += item.quantity,
+            None => lines.push((item.product_id, item.quantity))
+        }
+    }
+    let product_ids: Vec<Uuid> = lines.iter().map(|(product_id, _)| *product_id).collect();
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_scalar!(
+        "SELECT id FROM users WHERE id = $1 AND deleted_at IS NULL FOR SHARE",
+        body.user_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    match query_result {
+        Ok(Some(_)) => {},
+        Ok(None) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("There is no user with ID: {}", body.user_id)
+            });
+            return HttpResponse::UnprocessableEntity().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    // Products are locked in id order so concurrent orders cannot deadlock;
+    // prices and stock levels hold until the order commits.
+    let query_result = sqlx::query!(
+        "SELECT id, price, stock_quantity FROM products WHERE id = ANY($1) AND deleted_at IS NULL ORDER BY id FOR UPDATE",
+        &product_ids
+    )
+        .fetch_all(&mut tx)
+        .await;
+
+    let prices = match query_result {
+        Ok(prices) => prices,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let missing: Vec<Uuid> = product_ids
+        .iter()
+        .filter(|product_id| !prices.iter().any(|product| product.id == **product_id))
+        .copied()
+        .collect();
+    if !missing.is_empty() {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{} products in the order do not exist", missing.len()),
+            "missing_products": missing
+        });
+        return HttpResponse::UnprocessableEntity().json(json_error);
+    }
+
+    let mut quantities = Vec::with_capacity(lines.len());
+    let mut unit_prices = Vec::with_capacity(lines.len());
+    let mut total = BigDecimal::from(0);
+    for (product_id, quantity) in &lines {
+        let price = prices
+            .iter()
+            .find(|product| product.id == *product_id)
+            .map(|product| product.price.clone())
+            .unwrap_or_default();
+        total += &price * BigDecimal::from(*quantity);
+        quantities.push(*quantity);
+        unit_prices.push(price);
+    }
+
+    // Each line is fulfilled from a single warehouse; any shortfall rolls the
+    // whole order back.
+    let mut warehouse_ids = Vec::with_capacity(lines.len());
+    let mut out_of_stock = Vec::new();
+    for (product_id, quantity) in &lines {
+        match inventory::fulfil(&mut tx, *product_id, *quantity, data.config.fulfilment_strategy, body.ship_to.as_ref()).await {
+            Ok(Some(warehouse_id)) => warehouse_ids.push(warehouse_id),
+            Ok(None) => {
+                let available = prices
+                    .iter()
+                    .find(|product| product.id == *product_id)
+                    .map(|product| product.stock_quantity)
+                    .unwrap_or_default();
+                out_of_stock.push(json!({
+                    "product_id": product_id,
+                    "requested": quantity,
+                    "available": available
+                }));
+            },
+            Err(err) => {
+                let json_error = json!({
+                    "status": "error",
+                    "message": format!("{}", err)
+                });
+                return HttpResponse::InternalServerError().json(json_error);
+            }
+        }
+    }
+    if !out_of_stock.is_empty() {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{} products in the order are out of stock", out_of_stock.len()),
+            "out_of_stock": out_of_stock
+        });
+        return HttpResponse::Conflict().json(json_error);
+    }
+
+    let query_result = sqlx::query_as!(
+        OrderModel,
+        "INSERT INTO orders (user_id, currency, total) VALUES ($1, $2, $3) RETURNING *",
+        body.user_id,
+        data.config.currency,
+        total
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let order = match query_result {
+        Ok(order) => order,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+    let order_id = order.id;
+
+    let query_result = sqlx::query_as!(
+        OrderItemModel,
+        "INSERT INTO order_items (order_id, product_id, quantity, unit_price, warehouse_id)
+            SELECT $1, * FROM UNNEST($2::uuid[], $3::int[], $4::numeric[], $5::uuid[])
+            RETURNING *",
+        order_id,
+        &product_ids,
+        &quantities,
+        &unit_prices,
+        &warehouse_ids
+    )
+        .fetch_all(&mut tx)
+        .await;
+
+    let items = match query_result {
+        Ok(items) => items,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+    let order = OrderWithItems { order, items };
+
+    let query_result = audit::record(&mut tx, &audit, "create", "orders", order_id, None, audit::snapshot(&order)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": order
+    });
+    HttpResponse::Ok().json(json_response)
+}
+
+#[get("/{id}")]
+async fn get_order(data: web::Data<AppState>, path: web::Path<PathOptions>) -> impl Responder {
+    let order_id = path.into_inner().id;
+
+    let query_result = sqlx::query_as!(
+        OrderModel,
+        "SELECT * FROM orders WHERE id = $1",
+        order_id
+    )
+        .fetch_optional(&data.db)
+        .await;
+
+    let order = match query_result {
+        Ok(Some(order)) => order,
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no order with ID: {}", order_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let orders = match with_items(&data.db, vec![order]).await {
+        Ok(orders) => orders,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let json_response = json!({
+        "status": "success",
+        "data": orders.first()
+    });
+
+    HttpResponse::Ok().json(json_response)
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    let scope = web::scope("/orders")
+        .service(create_order)
+        .service(get_order);
+    cfg.service(scope);
+}
@@ -1,47 +1,114 @@
-use actix_web::{get, post, patch, delete, web, HttpResponse, Responder};
+use actix_web::{get, post, patch, delete, web, HttpResponse, HttpRequest, Responder, http::header::{ETag, IfMatch}};
 use chrono::Utc;
 use serde_json::json;
-use crate::{AppState, models::products::{ProductFilterOptions, ProductModel, CreateProduct, UpdateProduct}, schema::PathOptions};
+use json_patch::Patch as JsonPatch;
+use crate::{AppState, handlers::{questions, ratings, tags}, audit::{self, AuditContext}, auth::Admin, etag, hierarchy, inventory, patching, pricing, models::{tags::{self as tag_names, TagMatch}, products::{ProductFilterOptions, ProductModel, ProductListing, CreateProduct, UpdateProduct, PriceHistoryModel, PriceHistoryOptions, PriceAsOfOptions, StockAdjustmentModel, CreateStockAdjustment}, warehouses::{WarehouseStockModel, StockTransferModel, CreateStockTransfer}}, schema::{DeleteOptions, DeleteStrategy, DeletedOptions, FilterOptions, PathOptions}};
+
+/// Stock only changes through purchases and `POST /products/{id}/stock-adjustments`.
+const IMMUTABLE_FIELDS: [&str; 5] = ["id", "created_at", "updated_at", "deleted_at", "stock_quantity"];
 
 #[get("")]
-async fn get_products(data: web::Data<AppState>, opts: web::Query<ProductFilterOptions>) -> impl Responder {
+async fn get_products(req: HttpRequest, data: web::Data<AppState>, admin: Option<Admin>, opts: web::Query<ProductFilterOptions>) -> impl Responder {
     let limit = opts.limit.unwrap_or(10);
-    let offset = (opts.offset.unwrap_or(1) - 1) * 10;
+    let offset = opts.offset.unwrap_or(1).saturating_sub(1) * limit;
     let category_id = opts.category_id;
 
+    let include_deleted = opts.include_deleted.unwrap_or(false);
+    if include_deleted && admin.is_none() {
+        let json_error = json!({
+            "status": "error",
+            "message": "include_deleted requires admin credentials"
+        });
+        return HttpResponse::Forbidden().json(json_error);
+    }
+
+    let category_ids = match (category_id, opts.include_descendants.unwrap_or(false)) {
+        (Some(category_id), true) => match hierarchy::subtree_ids(&data.db, category_id).await {
+            Ok(ids) => Some(ids),
+            Err(err) => {
+                let json_error = json!({
+                    "status": "error",
+                    "message": format!("{}", err)
+                });
+                return HttpResponse::InternalServerError().json(json_error);
+            }
+        },
+        (category_id, _) => category_id.map(|category_id| vec![category_id])
+    };
+
+    let tag_filter: Option<Vec<String>> = opts.tags.as_deref()
+        .map(|tags| {
+            let mut names: Vec<String> = tags.split(',').map(tag_names::normalize).filter(|name| !name.is_empty()).collect();
+            names.sort();
+            names.dedup();
+            names
+        })
+        .filter(|names| !names.is_empty());
+    let match_all = opts.tag_match == Some(TagMatch::All);
+
+    // With match=all a product needs as many of the listed tags as were given.
     let query_result = sqlx::query_as!(
         ProductModel,
-        "SELECT * FROM products WHERE category_id = COALESCE($1, category_id) ORDER BY created_at LIMIT $2 OFFSET $3",
-        category_id,
+        "SELECT * FROM products WHERE ($1::uuid[] IS NULL OR category_id = ANY($1)) AND (deleted_at IS NULL OR $2)
+            AND ($5::text[] IS NULL OR (
+                SELECT COUNT(*) FROM product_tags pt JOIN tags t ON t.id = pt.tag_id WHERE pt.product_id = products.id AND t.tag_name = ANY($5)
+            ) >= CASE WHEN $6 THEN cardinality($5) ELSE 1 END)
+            ORDER BY created_at LIMIT $3 OFFSET $4",
+        category_ids.as_deref(),
+        include_deleted,
         limit as i32,
-        offset as i32
+        offset as i32,
+        tag_filter.as_deref(),
+        match_all
     )
         .fetch_all(&data.db)
         .await;
 
-    match query_result {
-        Ok(products) => {
-            let query_count = sqlx::query_scalar("SELECT COUNT(*) FROM products WHERE category_id = COALESCE($1, category_id)")
-                .bind(category_id)
-                .fetch_one(&data.db)
-                .await;
-            let product_count: i64 = match query_count {
-                Ok(count) => count,
-                Err(err) => {
-                    let json_error = json!({
-                        "status": "error",
-                        "message": format!("{}", err)
-                    });
-                    return HttpResponse::InternalServerError().json(json_error);
-                }
-            };
-            let json_response = json!({
-                "status": "success",
-                "count": product_count,
-                "data": products
+    let products = match query_result {
+        Ok(products) => products,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
             });
-            return HttpResponse::Ok().json(json_response)
-        },
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_count = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM products WHERE ($1::uuid[] IS NULL OR category_id = ANY($1)) AND (deleted_at IS NULL OR $2)
+            AND ($3::text[] IS NULL OR (
+                SELECT COUNT(*) FROM product_tags pt JOIN tags t ON t.id = pt.tag_id WHERE pt.product_id = products.id AND t.tag_name = ANY($3)
+            ) >= CASE WHEN $4 THEN cardinality($3) ELSE 1 END)"
+    )
+        .bind(category_ids.as_deref())
+        .bind(include_deleted)
+        .bind(tag_filter.as_deref())
+        .bind(match_all)
+        .fetch_one(&data.db)
+        .await;
+    let product_count: i64 = match query_count {
+        Ok(count) => count,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let ids: Vec<_> = products.iter().map(|product| product.id).collect();
+    let query_result = sqlx::query!(
+        r#"SELECT product_id AS "product_id!", rating_count AS "rating_count!", rating_sum::DOUBLE PRECISION / rating_count AS "average_rating!", last_rated_at
+            FROM product_rating_stats WHERE product_id = ANY($1)"#,
+        &ids
+    )
+        .fetch_all(&data.db)
+        .await;
+
+    let stats = match query_result {
+        Ok(stats) => stats,
         Err(err) => {
             let json_error = json!({
                 "status": "error",
@@ -50,62 +117,195 @@ async fn get_products(data: web::Data<AppState>, opts: web::Query<ProductFilterO
             return HttpResponse::InternalServerError().json(json_error);
         }
     };
+
+    // Rating changes show up in the tag once the stats view is refreshed.
+    let versions = products.iter().map(|product| (product.id, product.updated_at))
+        .chain(stats.iter().map(|stats| (stats.product_id, stats.last_rated_at)));
+    let etag = etag::collection_tag(versions, product_count);
+
+    let products: Vec<ProductListing> = products
+        .into_iter()
+        .map(|product| {
+            let stats = stats.iter().find(|stats| stats.product_id == product.id);
+            ProductListing {
+                rating_count: stats.map(|stats| stats.rating_count).unwrap_or(0),
+                average_rating: stats.map(|stats| stats.average_rating),
+                product
+            }
+        })
+        .collect();
+    let json_response = json!({
+        "status": "success",
+        "count": product_count,
+        "data": products
+    });
+    etag::conditional_response(&req, etag, None, &data.config.products_cache_control, json_response)
 }
 
 
 #[post("")]
-async fn create_product(data: web::Data<AppState>, body: web::Json<CreateProduct>) -> impl Responder {
+async fn create_product(data: web::Data<AppState>, audit: AuditContext, body: web::Json<CreateProduct>) -> impl Responder {
+    let stock_quantity = body.stock_quantity.unwrap_or(0);
+    if stock_quantity < 0 {
+        let json_error = json!({
+            "status": "error",
+            "message": "stock_quantity cannot be negative"
+        });
+        return HttpResponse::BadRequest().json(json_error);
+    }
+    let warehouse_id = match body.warehouse_id {
+        Some(warehouse_id) => Some(warehouse_id),
+        None if stock_quantity > 0 => {
+            let json_error = json!({
+                "status": "error",
+                "message": "warehouse_id is required when stock_quantity is set"
+            });
+            return HttpResponse::BadRequest().json(json_error);
+        },
+        None => None
+    };
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
     let query_result = sqlx::query_as!(
         ProductModel,
-        "INSERT INTO products (product_name, price, category_id) VALUES ($1, $2, $3) RETURNING *",
+        "INSERT INTO products (product_name, price, category_id, stock_quantity) VALUES ($1, $2, $3, $4) RETURNING *",
         body.product_name,
         body.price,
-        body.category_id
+        body.category_id,
+        stock_quantity
     )
-        .fetch_one(&data.db)
+        .fetch_one(&mut tx)
         .await;
 
-    match query_result {
-        Ok(product) => {
-            let json_response = json!({
-                "status": "success",
-                "data": product
-            });
-
-            return HttpResponse::Ok().json(json_response);
-        },
+    let product = match query_result {
+        Ok(product) => product,
         Err(err) => {
             let json_error = json!({
                 "status": "error",
                 "message": format!("{}", err)
             });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+    let product_id = product.id;
+
+    let query_result = audit::record(&mut tx, &audit, "create", "products", product_id, None, audit::snapshot(&product)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let query_result = pricing::record_price(&mut tx, product_id, &product.price, product.created_at.unwrap_or_else(Utc::now)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    // A supplied warehouse always gets a stock row, even an empty one, so the
+    // product is stocked there from the start.
+    if let Some(warehouse_id) = warehouse_id {
+        match inventory::warehouse_exists(&mut tx, warehouse_id).await {
+            Ok(true) => {},
+            Ok(false) => {
+                let json_error = json!({
+                    "status": "error",
+                    "message": format!("There is no warehouse with ID: {}", warehouse_id)
+                });
+                return HttpResponse::UnprocessableEntity().json(json_error);
+            },
+            Err(err) => {
+                let json_error = json!({
+                    "status": "error",
+                    "message": format!("{}", err)
+                });
+                return HttpResponse::InternalServerError().json(json_error);
+            }
+        }
 
+        // The product row already carries the total, so only the location is recorded here.
+        let query_result = sqlx::query!(
+            "INSERT INTO warehouse_stock (warehouse_id, product_id, quantity) VALUES ($1, $2, $3)",
+            warehouse_id,
+            product_id,
+            stock_quantity
+        )
+            .execute(&mut tx)
+            .await;
+        if let Err(err) = query_result {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
             return HttpResponse::InternalServerError().json(json_error);
         }
+
+        if stock_quantity > 0 {
+            let query_result = inventory::record_adjustment(&mut tx, warehouse_id, product_id, stock_quantity, stock_quantity, "initial stock", &audit.actor).await;
+            if let Err(err) = query_result {
+                let json_error = json!({
+                    "status": "error",
+                    "message": format!("{}", err)
+                });
+                return HttpResponse::InternalServerError().json(json_error);
+            }
+        }
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
     }
+
+    let json_response = json!({
+        "status": "success",
+        "data": product
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(product.updated_at))).json(json_response)
 }
 
 #[get("/{id}")]
-async fn get_product(data: web::Data<AppState>, path: web::Path<PathOptions>) -> impl Responder {
+async fn get_product(req: HttpRequest, data: web::Data<AppState>, admin: Option<Admin>, path: web::Path<PathOptions>, opts: web::Query<DeletedOptions>) -> impl Responder {
     let product_id = path.into_inner().id;
 
+    let include_deleted = opts.include_deleted.unwrap_or(false);
+    if include_deleted && admin.is_none() {
+        let json_error = json!({
+            "status": "error",
+            "message": "include_deleted requires admin credentials"
+        });
+        return HttpResponse::Forbidden().json(json_error);
+    }
+
     let query_result = sqlx::query_as!(
         ProductModel,
-        "SELECT * FROM products WHERE id = $1",
-        product_id
+        "SELECT * FROM products WHERE id = $1 AND (deleted_at IS NULL OR $2)",
+        product_id,
+        include_deleted
     )
         .fetch_one(&data.db)
         .await;
 
-    match query_result {
-        Ok(product) => {
-            let json_response = json!({
-                "status": "success",
-                "data": product
-            });
-
-            return HttpResponse::Ok().json(json_response);
-        },
+    let product = match query_result {
+        Ok(product) => product,
         Err(err) => {
             let json_error = json!({
                 "status": "error",
@@ -113,20 +313,40 @@ async fn get_product(data: web::Data<AppState>, path: web::Path<PathOptions>) ->
             });
             return HttpResponse::InternalServerError().json(json_error);
         }
-    }
+    };
+
+    let etag = etag::entity_tag(product.updated_at);
+    let last_modified = product.updated_at;
+    let json_response = json!({
+        "status": "success",
+        "data": product
+    });
+
+    etag::conditional_response(&req, etag, last_modified, &data.config.products_cache_control, json_response)
 }
 
 
-#[patch("/{id}")]
-async fn update_product(data: web::Data<AppState>, path: web::Path<PathOptions>, body: web::Json<UpdateProduct>) -> impl Responder {
+#[patch("/{id}", guard = "patching::is_json_patch")]
+async fn json_patch_product(data: web::Data<AppState>, audit: AuditContext, path: web::Path<PathOptions>, if_match: Option<web::Header<IfMatch>>, body: web::Json<JsonPatch>) -> impl Responder {
     let product_id = path.into_inner().id;
 
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
     let query_result = sqlx::query_as!(
         ProductModel,
-        "SELECT * FROM products WHERE id = $1",
+        "SELECT * FROM products WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
         product_id
     )
-        .fetch_one(&data.db)
+        .fetch_one(&mut tx)
         .await;
 
     let product = match query_result {
@@ -139,61 +359,1066 @@ async fn update_product(data: web::Data<AppState>, path: web::Path<PathOptions>,
             return HttpResponse::InternalServerError().json(json_error);
         }
     };
+    let before = audit::snapshot(&product);
+    let previous_price = product.price.clone();
+
+    if !etag::if_match(&if_match, &etag::entity_tag(product.updated_at)) {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("Product {} has been modified since it was fetched", product_id)
+        });
+        return HttpResponse::PreconditionFailed().json(json_error);
+    }
+
+    let product: ProductModel = match patching::apply(&product, &body, &IMMUTABLE_FIELDS) {
+        Ok(product) => product,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::build(err.status()).json(json_error);
+        }
+    };
 
     let now = Utc::now();
 
     let query_result = sqlx::query_as!(
         ProductModel,
         "UPDATE products SET product_name = $1, price = $2, category_id = $3, updated_at = $4 WHERE id = $5 RETURNING *",
-        body.product_name.to_owned().unwrap_or(product.product_name),
-        body.price.to_owned().unwrap_or(product.price),
-        body.category_id.to_owned().unwrap_or(product.category_id),
+        product.product_name,
+        product.price,
+        product.category_id,
         now,
         product_id
     )
-        .fetch_one(&data.db)
+        .fetch_one(&mut tx)
         .await;
 
-    match query_result {
-        Ok(product) => {
-            let json_response = json!({
-                "status": "success",
-                "data": product
-            });
-            return HttpResponse::Ok().json(json_response);
-        },
+    let product = match query_result {
+        Ok(product) => product,
         Err(err) => {
             let json_error = json!({
                 "status": "error",
                 "message": format!("{}", err)
             });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
 
-            return HttpResponse::InternalServerError().json(json_error)
+    if product.price != previous_price {
+        let query_result = pricing::record_price(&mut tx, product_id, &product.price, now).await;
+        if let Err(err) = query_result {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
         }
     }
+
+    let query_result = audit::record(&mut tx, &audit, "update", "products", product_id, before, audit::snapshot(&product)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": product
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(product.updated_at))).json(json_response)
 }
 
-#[delete("/{id}")]
-async fn delete_product(data: web::Data<AppState>, path: web::Path<PathOptions>) -> impl Responder {
+#[patch("/{id}")]
+async fn update_product(data: web::Data<AppState>, audit: AuditContext, path: web::Path<PathOptions>, if_match: Option<web::Header<IfMatch>>, body: web::Json<UpdateProduct>) -> impl Responder {
     let product_id = path.into_inner().id;
 
-    let query_result = sqlx::query!("DELETE FROM products WHERE id = $1", product_id)
-        .execute(&data.db)
-        .await
-        .unwrap()
-        .rows_affected();
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        ProductModel,
+        "SELECT * FROM products WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+        product_id
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let product = match query_result {
+        Ok(product) => product,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+    let before = audit::snapshot(&product);
+    let previous_price = product.price.clone();
 
-    if query_result == 0 {
+    if !etag::if_match(&if_match, &etag::entity_tag(product.updated_at)) {
         let json_error = json!({
             "status": "error",
-            "message": format!("No results found with ID: {}", product_id)
+            "message": format!("Product {} has been modified since it was fetched", product_id)
         });
-        return HttpResponse::InternalServerError().json(json_error)
+        return HttpResponse::PreconditionFailed().json(json_error);
+    }
+
+    let product = match body.into_inner().merge_into(product) {
+        Ok(product) => product,
+        Err(message) => {
+            let json_error = json!({
+                "status": "error",
+                "message": message
+            });
+            return HttpResponse::BadRequest().json(json_error);
+        }
+    };
+
+    let now = Utc::now();
+
+    let query_result = sqlx::query_as!(
+        ProductModel,
+        "UPDATE products SET product_name = $1, price = $2, category_id = $3, updated_at = $4 WHERE id = $5 RETURNING *",
+        product.product_name,
+        product.price,
+        product.category_id,
+        now,
+        product_id
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let product = match query_result {
+        Ok(product) => product,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
     };
 
+    if product.price != previous_price {
+        let query_result = pricing::record_price(&mut tx, product_id, &product.price, now).await;
+        if let Err(err) = query_result {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    let query_result = audit::record(&mut tx, &audit, "update", "products", product_id, before, audit::snapshot(&product)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
     let json_response = json!({
         "status": "success",
-        "message": "successfully deleted"
+        "data": product
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(product.updated_at))).json(json_response)
+}
+
+#[delete("/{id}")]
+async fn delete_product(data: web::Data<AppState>, audit: AuditContext, path: web::Path<PathOptions>, if_match: Option<web::Header<IfMatch>>, opts: web::Query<DeleteOptions>) -> impl Responder {
+    let product_id = path.into_inner().id;
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        ProductModel,
+        "SELECT * FROM products WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+        product_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let product = match query_result {
+        Ok(product) => product,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    if let Some(product) = &product {
+        if !etag::if_match(&if_match, &etag::entity_tag(product.updated_at)) {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("Product {} has been modified since it was fetched", product_id)
+            });
+            return HttpResponse::PreconditionFailed().json(json_error);
+        }
+    }
+
+    let now = Utc::now();
+
+    if product.is_some() {
+        match opts.strategy() {
+            DeleteStrategy::Restrict => {
+                let query_result = sqlx::query_scalar!(
+                    "SELECT id FROM ratings WHERE product_id = $1 AND deleted_at IS NULL",
+                    product_id
+                )
+                    .fetch_all(&mut tx)
+                    .await;
+
+                let ratings = match query_result {
+                    Ok(ids) => ids,
+                    Err(err) => {
+                        let json_error = json!({
+                            "status": "error",
+                            "message": format!("{}", err)
+                        });
+                        return HttpResponse::InternalServerError().json(json_error);
+                    }
+                };
+
+                let query_result = sqlx::query_scalar!(
+                    "SELECT id FROM purchases WHERE product_id = $1 AND deleted_at IS NULL",
+                    product_id
+                )
+                    .fetch_all(&mut tx)
+                    .await;
+
+                let purchases = match query_result {
+                    Ok(ids) => ids,
+                    Err(err) => {
+                        let json_error = json!({
+                            "status": "error",
+                            "message": format!("{}", err)
+                        });
+                        return HttpResponse::InternalServerError().json(json_error);
+                    }
+                };
+
+                if !ratings.is_empty() || !purchases.is_empty() {
+                    let json_error = json!({
+                        "status": "error",
+                        "message": format!("Product {} still has {} ratings and {} purchases", product_id, ratings.len(), purchases.len()),
+                        "dependents": {
+                            "ratings": ratings,
+                            "purchases": purchases
+                        }
+                    });
+                    return HttpResponse::Conflict().json(json_error);
+                }
+            },
+            DeleteStrategy::Reassign => {
+                let json_error = json!({
+                    "status": "error",
+                    "message": "Products cannot be reassigned, use strategy=restrict or strategy=cascade"
+                });
+                return HttpResponse::BadRequest().json(json_error);
+            },
+            DeleteStrategy::Cascade => {
+                let query_result = sqlx::query!(
+                    "WITH affected AS (\n                        UPDATE ratings SET deleted_at = $1, updated_at = $1 FROM ratings old\n                        WHERE ratings.id = old.id AND ratings.product_id = $2 AND ratings.deleted_at IS NULL\n                        RETURNING ratings.id, to_jsonb(old) AS before, to_jsonb(ratings) AS after\n                    )\n                    INSERT INTO audit_log (actor, action, resource_type, resource_id, before, after, request_id)\n                    SELECT $3, 'delete', 'ratings', id, before, after, $4 FROM affected",
+                    now,
+                    product_id,
+                    audit.actor,
+                    audit.request_id
+                )
+                    .execute(&mut tx)
+                    .await;
+
+                if let Err(err) = query_result {
+                    let json_error = json!({
+                        "status": "error",
+                        "message": format!("{}", err)
+                    });
+                    return HttpResponse::InternalServerError().json(json_error);
+                }
+
+                let query_result = sqlx::query!(
+                    r#"WITH affected AS (
+                            UPDATE purchases SET deleted_at = $1, updated_at = $1 FROM purchases old
+                            WHERE purchases.id = old.id AND purchases.product_id = $2 AND purchases.deleted_at IS NULL
+                            RETURNING purchases.*, to_jsonb(old) AS before, to_jsonb(purchases) AS after
+                        ), audited AS (
+                            INSERT INTO audit_log (actor, action, resource_type, resource_id, before, after, request_id)
+                            SELECT $3, 'delete', 'purchases', id, before, after, $4 FROM affected
+                        )
+                        SELECT product_id AS "product_id!", warehouse_id AS "warehouse_id!", quantity AS "quantity!", status AS "status!" FROM affected"#,
+                    now,
+                    product_id,
+                    audit.actor,
+                    audit.request_id
+                )
+                    .fetch_all(&mut tx)
+                    .await;
+
+                let deleted = match query_result {
+                    Ok(deleted) => deleted,
+                    Err(err) => {
+                        let json_error = json!({
+                            "status": "error",
+                            "message": format!("{}", err)
+                        });
+                        return HttpResponse::InternalServerError().json(json_error);
+                    }
+                };
+
+                // Deleted purchases no longer hold stock.
+                for purchase in deleted {
+                    if let Err(err) = inventory::release_purchase(&mut tx, &purchase.status, purchase.warehouse_id, purchase.product_id, purchase.quantity).await {
+                        let json_error = json!({
+                            "status": "error",
+                            "message": format!("{}", err)
+                        });
+                        return HttpResponse::InternalServerError().json(json_error);
+                    }
+                }
+            }
+        }
+    }
+
+    let query_result = sqlx::query!(
+        "UPDATE products SET deleted_at = $1, updated_at = $1 WHERE id = $2 AND deleted_at IS NULL",
+        now,
+        product_id
+    )
+        .execute(&mut tx)
+        .await;
+
+    let rows_affected = match query_result {
+        Ok(result) => result.rows_affected(),
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    if rows_affected == 0 {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("No results found with ID: {}", product_id)
+        });
+        return HttpResponse::InternalServerError().json(json_error)
+    };
+
+    let query_result = audit::record(&mut tx, &audit, "delete", "products", product_id, product.as_ref().and_then(audit::snapshot), None).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "message": "successfully deleted"
+    });
+
+    HttpResponse::Ok().json(json_response)
+}
+
+#[get("/trash")]
+async fn get_deleted_products(data: web::Data<AppState>, _admin: Admin, opts: web::Query<FilterOptions>) -> impl Responder {
+    let limit = opts.limit.unwrap_or(10);
+    let offset = opts.offset.unwrap_or(1).saturating_sub(1) * limit;
+
+    let query_result = sqlx::query_as!(
+        ProductModel,
+        "SELECT * FROM products WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC LIMIT $1 OFFSET $2",
+        limit as i32,
+        offset as i32
+    )
+        .fetch_all(&data.db)
+        .await;
+
+    let products = match query_result {
+        Ok(products) => products,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_count = sqlx::query_scalar("SELECT COUNT(*) FROM products WHERE deleted_at IS NOT NULL")
+        .fetch_one(&data.db)
+        .await;
+
+    let deleted_count: i64 = match query_count {
+        Ok(count) => count,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+    let json_response = json!({
+        "status": "success",
+        "count": deleted_count,
+        "data": products
+    });
+
+    HttpResponse::Ok().json(json_response)
+}
+
+#[post("/{id}/restore")]
+async fn restore_product(data: web::Data<AppState>, _admin: Admin, audit: AuditContext, path: web::Path<PathOptions>) -> impl Responder {
+    let product_id = path.into_inner().id;
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        ProductModel,
+        "SELECT * FROM products WHERE id = $1 AND deleted_at IS NOT NULL FOR UPDATE",
+        product_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let before = match query_result {
+        Ok(Some(product)) => audit::snapshot(&product),
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no deleted product with ID: {}", product_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let now = Utc::now();
+
+    let query_result = sqlx::query_as!(
+        ProductModel,
+        "UPDATE products SET deleted_at = NULL, updated_at = $1 WHERE id = $2 RETURNING *",
+        now,
+        product_id
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let product = match query_result {
+        Ok(product) => product,
+        Err(sqlx::Error::Database(err)) if err.code().as_deref() == Some("23505") => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("Cannot restore product {}: {}", product_id, err)
+            });
+            return HttpResponse::Conflict().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = audit::record(&mut tx, &audit, "restore", "products", product_id, before, audit::snapshot(&product)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": product
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(product.updated_at))).json(json_response)
+}
+
+#[get("/{id}/price-history")]
+async fn get_price_history(data: web::Data<AppState>, path: web::Path<PathOptions>, opts: web::Query<PriceHistoryOptions>) -> impl Responder {
+    let product_id = path.into_inner().id;
+
+    let query_result = sqlx::query_as!(
+        PriceHistoryModel,
+        r#"SELECT id AS "id!", product_id AS "product_id!", price AS "price!", valid_from AS "valid_from!", valid_to, created_at
+            FROM (
+                SELECT *, LEAD(valid_from) OVER (ORDER BY valid_from) AS valid_to
+                FROM product_price_history WHERE product_id = $1
+            ) history
+            WHERE ($2::timestamptz IS NULL OR valid_to IS NULL OR valid_to > $2)
+                AND ($3::timestamptz IS NULL OR valid_from <= $3)
+            ORDER BY valid_from"#,
+        product_id,
+        opts.from,
+        opts.to
+    )
+        .fetch_all(&data.db)
+        .await;
+
+    let history = match query_result {
+        Ok(history) => history,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let json_response = json!({
+        "status": "success",
+        "count": history.len(),
+        "data": history
+    });
+
+    HttpResponse::Ok().json(json_response)
+}
+
+#[get("/{id}/price")]
+async fn get_price_as_of(data: web::Data<AppState>, path: web::Path<PathOptions>, opts: web::Query<PriceAsOfOptions>) -> impl Responder {
+    let product_id = path.into_inner().id;
+    let at = opts.at.unwrap_or_else(Utc::now);
+
+    let price = match pricing::price_as_of(&data.db, product_id, at).await {
+        Ok(Some(price)) => price,
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("Product {} had no price at {}", product_id, at)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let json_response = json!({
+        "status": "success",
+        "data": {
+            "product_id": product_id,
+            "price": price,
+            "at": at
+        }
+    });
+
+    HttpResponse::Ok().json(json_response)
+}
+
+#[post("/{id}/stock-adjustments")]
+async fn create_stock_adjustment(data: web::Data<AppState>, _admin: Admin, audit: AuditContext, path: web::Path<PathOptions>, body: web::Json<CreateStockAdjustment>) -> impl Responder {
+    let product_id = path.into_inner().id;
+
+    if body.delta == 0 {
+        let json_error = json!({
+            "status": "error",
+            "message": "delta must not be zero"
+        });
+        return HttpResponse::BadRequest().json(json_error);
+    }
+    if body.reason.trim().is_empty() {
+        let json_error = json!({
+            "status": "error",
+            "message": "reason is required"
+        });
+        return HttpResponse::BadRequest().json(json_error);
+    }
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        ProductModel,
+        "SELECT * FROM products WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+        product_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let product = match query_result {
+        Ok(Some(product)) => product,
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no product with ID: {}", product_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+    let before = audit::snapshot(&product);
+
+    let warehouse_id = body.warehouse_id;
+    match inventory::warehouse_exists(&mut tx, warehouse_id).await {
+        Ok(true) => {},
+        Ok(false) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("There is no warehouse with ID: {}", warehouse_id)
+            });
+            return HttpResponse::UnprocessableEntity().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    let query_result = if body.delta > 0 {
+        inventory::release(&mut tx, warehouse_id, product_id, body.delta).await.map(Some)
+    } else {
+        inventory::take(&mut tx, warehouse_id, product_id, -body.delta).await
+    };
+
+    let quantity_after = match query_result {
+        Ok(Some(quantity_after)) => quantity_after,
+        Ok(None) => {
+            let level = inventory::level(&mut tx, warehouse_id, product_id).await.unwrap_or(0);
+            let json_error = json!({
+                "status": "error",
+                "message": format!("Warehouse {} has only {} of product {} in stock", warehouse_id, level, product_id)
+            });
+            return HttpResponse::Conflict().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        ProductModel,
+        "SELECT * FROM products WHERE id = $1",
+        product_id
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let product = match query_result {
+        Ok(product) => product,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = inventory::record_adjustment(&mut tx, warehouse_id, product_id, body.delta, quantity_after, body.reason.trim(), &audit.actor).await;
+    let adjustment = match query_result {
+        Ok(adjustment) => adjustment,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = audit::record(&mut tx, &audit, "update", "products", product_id, before, audit::snapshot(&product)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": adjustment
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(product.updated_at))).json(json_response)
+}
+
+#[get("/{id}/stock-adjustments")]
+async fn get_stock_adjustments(data: web::Data<AppState>, _admin: Admin, path: web::Path<PathOptions>, opts: web::Query<FilterOptions>) -> impl Responder {
+    let product_id = path.into_inner().id;
+    let limit = opts.limit.unwrap_or(10);
+    let offset = opts.offset.unwrap_or(1).saturating_sub(1) * limit;
+
+    let query_result = sqlx::query_as!(
+        StockAdjustmentModel,
+        "SELECT * FROM stock_adjustments WHERE product_id = $1 ORDER BY created_at DESC LIMIT $2 OFFSET $3",
+        product_id,
+        limit as i32,
+        offset as i32
+    )
+        .fetch_all(&data.db)
+        .await;
+
+    let adjustments = match query_result {
+        Ok(adjustments) => adjustments,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let json_response = json!({
+        "status": "success",
+        "count": adjustments.len(),
+        "data": adjustments
+    });
+
+    HttpResponse::Ok().json(json_response)
+}
+
+#[get("/{id}/availability")]
+async fn get_availability(data: web::Data<AppState>, path: web::Path<PathOptions>) -> impl Responder {
+    let product_id = path.into_inner().id;
+
+    let stock_quantity = match inventory::on_hand(&data.db, product_id).await {
+        Ok(Some(stock_quantity)) => stock_quantity,
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no product with ID: {}", product_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        WarehouseStockModel,
+        "SELECT s.warehouse_id, w.warehouse_name, s.quantity
+            FROM warehouse_stock s JOIN warehouses w ON w.id = s.warehouse_id
+            WHERE s.product_id = $1 AND s.quantity > 0
+            ORDER BY w.warehouse_name",
+        product_id
+    )
+        .fetch_all(&data.db)
+        .await;
+
+    let locations = match query_result {
+        Ok(locations) => locations,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let json_response = json!({
+        "status": "success",
+        "data": {
+            "product_id": product_id,
+            "stock_quantity": stock_quantity,
+            "locations": locations
+        }
+    });
+
+    HttpResponse::Ok().json(json_response)
+}
+
+#[post("/{id}/stock-transfers")]
+async fn create_stock_transfer(data: web::Data<AppState>, _admin: Admin, audit: AuditContext, path: web::Path<PathOptions>, body: web::Json<CreateStockTransfer>) -> impl Responder {
+    let product_id = path.into_inner().id;
+
+    if body.quantity < 1 {
+        let json_error = json!({
+            "status": "error",
+            "message": "quantity must be at least 1"
+        });
+        return HttpResponse::BadRequest().json(json_error);
+    }
+    if body.from_warehouse_id == body.to_warehouse_id {
+        let json_error = json!({
+            "status": "error",
+            "message": "from_warehouse_id and to_warehouse_id must differ"
+        });
+        return HttpResponse::BadRequest().json(json_error);
+    }
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_scalar!(
+        "SELECT id FROM products WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+        product_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    match query_result {
+        Ok(Some(_)) => {},
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no product with ID: {}", product_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    match inventory::warehouse_exists(&mut tx, body.to_warehouse_id).await {
+        Ok(true) => {},
+        Ok(false) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("There is no warehouse with ID: {}", body.to_warehouse_id)
+            });
+            return HttpResponse::UnprocessableEntity().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    match inventory::take(&mut tx, body.from_warehouse_id, product_id, body.quantity).await {
+        Ok(Some(_)) => {},
+        Ok(None) => {
+            let level = inventory::level(&mut tx, body.from_warehouse_id, product_id).await.unwrap_or(0);
+            let json_error = json!({
+                "status": "error",
+                "message": format!("Warehouse {} has only {} of product {} in stock", body.from_warehouse_id, level, product_id)
+            });
+            return HttpResponse::Conflict().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    if let Err(err) = inventory::release(&mut tx, body.to_warehouse_id, product_id, body.quantity).await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let query_result = sqlx::query_as!(
+        StockTransferModel,
+        "INSERT INTO stock_transfers (product_id, from_warehouse_id, to_warehouse_id, quantity, reason, actor) VALUES ($1, $2, $3, $4, $5, $6) RETURNING *",
+        product_id,
+        body.from_warehouse_id,
+        body.to_warehouse_id,
+        body.quantity,
+        body.reason,
+        audit.actor
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let transfer = match query_result {
+        Ok(transfer) => transfer,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = audit::record(&mut tx, &audit, "create", "stock_transfers", transfer.id, None, audit::snapshot(&transfer)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": transfer
+    });
+    HttpResponse::Ok().json(json_response)
+}
+
+#[get("/{id}/stock-transfers")]
+async fn get_stock_transfers(data: web::Data<AppState>, _admin: Admin, path: web::Path<PathOptions>, opts: web::Query<FilterOptions>) -> impl Responder {
+    let product_id = path.into_inner().id;
+    let limit = opts.limit.unwrap_or(10);
+    let offset = opts.offset.unwrap_or(1).saturating_sub(1) * limit;
+
+    let query_result = sqlx::query_as!(
+        StockTransferModel,
+        "SELECT * FROM stock_transfers WHERE product_id = $1 ORDER BY created_at DESC LIMIT $2 OFFSET $3",
+        product_id,
+        limit as i32,
+        offset as i32
+    )
+        .fetch_all(&data.db)
+        .await;
+
+    let transfers = match query_result {
+        Ok(transfers) => transfers,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let json_response = json!({
+        "status": "success",
+        "count": transfers.len(),
+        "data": transfers
     });
 
     HttpResponse::Ok().json(json_response)
@@ -202,10 +1427,27 @@ async fn delete_product(data: web::Data<AppState>, path: web::Path<PathOptions>)
 pub fn config(cfg: &mut web::ServiceConfig) {
     let scope = web::scope("/products")
         .service(get_products)
+        .service(get_deleted_products)
         .service(create_product)
         .service(get_product)
+        .service(get_price_history)
+        .service(get_price_as_of)
+        .service(get_stock_adjustments)
+        .service(create_stock_adjustment)
+        .service(get_availability)
+        .service(ratings::put_my_rating)
+        .service(ratings::get_product_rating_summary)
+        .service(questions::get_product_questions)
+        .service(questions::ask_question)
+        .service(tags::get_product_tags)
+        .service(tags::attach_tag)
+        .service(tags::detach_tag)
+        .service(get_stock_transfers)
+        .service(create_stock_transfer)
+        .service(json_patch_product)
         .service(update_product)
-        .service(delete_product);
+        .service(delete_product)
+        .service(restore_product);
     
     cfg.service(scope);
 }
\ No newline at end of file
@@ -1,21 +1,46 @@
-use actix_web::{get, post, patch, delete, HttpResponse, Responder, web};
+use actix_web::{get, post, patch, delete, HttpResponse, Responder, web, http::header::{ETag, IfMatch}};
 use chrono::Utc;
 use serde_json::json;
+use json_patch::Patch as JsonPatch;
+use uuid::Uuid;
 
-use crate::{AppState, models::purchases::{PurchaseFilterOptions, PurchaseModel, CreatePurchase, UpdatePurchase}, schema::PathOptions};
+use crate::{AppState, audit::{self, AuditContext}, auth::Admin, etag, inventory, patching, pricing, models::purchases::{PurchaseFilterOptions, PurchaseModel, CreatePurchase, UpdatePurchase, PurchaseStatus, PurchaseTransitionModel, CreatePurchaseTransition}, schema::{DeletedOptions, FilterOptions, PathOptions}};
+
+/// Prices are captured at purchase time and `total` is computed by the database.
+/// Status only changes through `POST /purchases/{id}/transitions`, the
+/// fulfilling warehouse is picked by the server, and guest purchases only
+/// gain an owner through the claim flow.
+const IMMUTABLE_FIELDS: [&str; 12] = [
+    "id", "created_at", "updated_at", "deleted_at", "unit_price", "currency", "total", "status", "warehouse_id", "guest_email",
+    "user_id", "claim_token"
+];
 
 #[get("")]
-async fn get_purchases(data: web::Data<AppState>, opts: web::Query<PurchaseFilterOptions>) -> impl Responder {
+async fn get_purchases(data: web::Data<AppState>, admin: Option<Admin>, opts: web::Query<PurchaseFilterOptions>) -> impl Responder {
     let limit = opts.limit.unwrap_or(10);
-    let offset = (opts.offset.unwrap_or(1) - 1) * limit;
+    let offset = opts.offset.unwrap_or(1).saturating_sub(1) * limit;
     let product_id = opts.product_id;
     let user_id = opts.user_id;
+    let status = opts.status.map(|status| status.as_str());
+
+    let include_deleted = opts.include_deleted.unwrap_or(false);
+    if include_deleted && admin.is_none() {
+        let json_error = json!({
+            "status": "error",
+            "message": "include_deleted requires admin credentials"
+        });
+        return HttpResponse::Forbidden().json(json_error);
+    }
 
     let query_result = sqlx::query_as!(
         PurchaseModel,
-        "SELECT * FROM purchases WHERE product_id = COALESCE($1, product_id) AND user_id = COALESCE($2, user_id) ORDER BY created_at LIMIT $3 OFFSET $4",
+        "SELECT * FROM purchases WHERE product_id = COALESCE($1, product_id) AND ($2::uuid IS NULL OR user_id = $2) AND (deleted_at IS NULL OR $3) AND ($4::timestamptz IS NULL OR created_at >= $4) AND ($5::timestamptz IS NULL OR created_at < $5) AND status = COALESCE($6, status) ORDER BY created_at LIMIT $7 OFFSET $8",
         product_id,
         user_id,
+        include_deleted,
+        opts.from,
+        opts.to,
+        status,
         limit as i32, 
         offset as i32
     )
@@ -23,32 +48,8 @@ async fn get_purchases(data: web::Data<AppState>, opts: web::Query<PurchaseFilte
         .await;
 
 
-    match query_result {
-        Ok(purchases) => {
-            let query_count = sqlx::query_scalar("SELECT COUNT(*) FROM purchases WHERE product_id = COALESCE($1, product_id) AND user_id = COALESCE($2, user_id)")
-                .bind(product_id)
-                .bind(user_id)
-                .fetch_one(&data.db)
-                .await;
-
-            let purchase_count: i64 = match query_count {
-                Ok(count) => count,
-                Err(err) => {
-                    let json_error = json!({
-                        "status": "error",
-                        "data": format!("{}", err)
-                    });
-                    return HttpResponse::InternalServerError().json(json_error);
-                }
-            };
-            let json_response = json!({
-                "status": "success",
-                "count": purchase_count,
-                "data": purchases
-            });
-
-            return HttpResponse::Ok().json(json_response);
-        },
+    let purchases = match query_result {
+        Ok(purchases) => purchases,
         Err(err) => {
             let json_error = json!({
                 "status": "error",
@@ -58,62 +59,209 @@ async fn get_purchases(data: web::Data<AppState>, opts: web::Query<PurchaseFilte
             return HttpResponse::InternalServerError().json(json_error);
             
         }
-    }
+    };
+
+    let query_count = sqlx::query_scalar("SELECT COUNT(*) FROM purchases WHERE product_id = COALESCE($1, product_id) AND ($2::uuid IS NULL OR user_id = $2) AND (deleted_at IS NULL OR $3) AND ($4::timestamptz IS NULL OR created_at >= $4) AND ($5::timestamptz IS NULL OR created_at < $5) AND status = COALESCE($6, status)")
+        .bind(product_id)
+        .bind(user_id)
+        .bind(include_deleted)
+        .bind(opts.from)
+        .bind(opts.to)
+        .bind(status)
+        .fetch_one(&data.db)
+        .await;
+
+    let purchase_count: i64 = match query_count {
+        Ok(count) => count,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "data": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+    let json_response = json!({
+        "status": "success",
+        "count": purchase_count,
+        "data": purchases
+    });
+
+    HttpResponse::Ok().json(json_response)
 }
 
 
 #[post("")]
-async fn create_purchase(data: web::Data<AppState>, body: web::Json<CreatePurchase>) -> impl Responder {
-    let query_result = sqlx::query_as!(
-        PurchaseModel,
-        "INSERT INTO purchases (product_id, user_id) VALUES ($1, $2) RETURNING *",
-        body.product_id,
-        body.user_id
+async fn create_purchase(data: web::Data<AppState>, audit: AuditContext, body: web::Json<CreatePurchase>) -> impl Responder {
+    let quantity = body.quantity.unwrap_or(1);
+    if quantity < 1 {
+        let json_error = json!({
+            "status": "error",
+            "message": "quantity must be at least 1"
+        });
+        return HttpResponse::BadRequest().json(json_error);
+    }
+
+    // Guests are identified by their contact email and get a claim token in
+    // place of an account.
+    let guest_email = body.guest_email.as_deref().map(str::trim);
+    let claim_token = match (body.user_id, guest_email) {
+        (Some(_), None) => None,
+        (None, Some(email)) if email.contains('@') => Some(Uuid::new_v4()),
+        (None, Some(_)) => {
+            let json_error = json!({
+                "status": "error",
+                "message": "guest_email must be an email address"
+            });
+            return HttpResponse::BadRequest().json(json_error);
+        },
+        _ => {
+            let json_error = json!({
+                "status": "error",
+                "message": "Exactly one of user_id and guest_email is required"
+            });
+            return HttpResponse::BadRequest().json(json_error);
+        }
+    };
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    // The product row stays locked until commit, so the captured price and
+    // the stock check cannot be raced by another purchase.
+    let query_result = sqlx::query!(
+        "SELECT price, stock_quantity FROM products WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+        body.product_id
     )
-        .fetch_one(&data.db)
+        .fetch_optional(&mut tx)
         .await;
 
-    match query_result {
-        Ok(purchase) => {
-            let json_response = json!({
-                "status": "success",
-                "data": purchase
+    let product = match query_result {
+        Ok(Some(product)) => product,
+        Ok(None) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("There is no product with ID: {}", body.product_id)
             });
-
-            return HttpResponse::Ok().json(json_response);
+            return HttpResponse::UnprocessableEntity().json(json_error);
         },
         Err(err) => {
             let json_error = json!({
                 "status": "error",
                 "message": format!("{}", err)
             });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    if product.stock_quantity < quantity {
+        return out_of_stock(body.product_id, quantity, product.stock_quantity);
+    }
+
+    let query_result = inventory::fulfil(&mut tx, body.product_id, quantity, data.config.fulfilment_strategy, body.ship_to.as_ref()).await;
+    let warehouse_id = match query_result {
+        Ok(Some(warehouse_id)) => warehouse_id,
+        Ok(None) => return out_of_stock(body.product_id, quantity, product.stock_quantity),
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        PurchaseModel,
+        "INSERT INTO purchases (product_id, user_id, quantity, unit_price, currency, warehouse_id, guest_email, claim_token) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING *",
+        body.product_id,
+        body.user_id,
+        quantity,
+        product.price,
+        data.config.currency,
+        warehouse_id,
+        guest_email,
+        claim_token
+    )
+        .fetch_one(&mut tx)
+        .await;
 
+    let purchase = match query_result {
+        Ok(purchase) => purchase,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
             return HttpResponse::InternalServerError().json(json_error);
         }
+    };
+    let purchase_id = purchase.id;
+
+    let query_result = audit::record(&mut tx, &audit, "create", "purchases", purchase_id, None, audit::snapshot(&purchase)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
     }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = match purchase.claim_token {
+        Some(claim_token) => json!({
+            "status": "success",
+            "data": purchase,
+            "claim_token": claim_token,
+            "guest_email": purchase.guest_email
+        }),
+        None => json!({
+            "status": "success",
+            "data": purchase
+        })
+    };
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(purchase.updated_at))).json(json_response)
 }
 
 #[get("/{id}")]
-async fn get_purchase(data: web::Data<AppState>, path: web::Path<PathOptions>) -> impl Responder {
+async fn get_purchase(data: web::Data<AppState>, admin: Option<Admin>, path: web::Path<PathOptions>, opts: web::Query<DeletedOptions>) -> impl Responder {
     let purchase_id = path.into_inner().id;
 
+    let include_deleted = opts.include_deleted.unwrap_or(false);
+    if include_deleted && admin.is_none() {
+        let json_error = json!({
+            "status": "error",
+            "message": "include_deleted requires admin credentials"
+        });
+        return HttpResponse::Forbidden().json(json_error);
+    }
+
     let query_result = sqlx::query_as!(
         PurchaseModel,
-        "SELECT * FROM purchases WHERE id = $1",
-        purchase_id
+        "SELECT * FROM purchases WHERE id = $1 AND (deleted_at IS NULL OR $2)",
+        purchase_id,
+        include_deleted
     )
         .fetch_one(&data.db)
         .await;
 
-    match query_result {
-        Ok(purchase) => {
-            let json_response = json!({
-                "status": "success",
-                "data": purchase
-            });
-
-            return HttpResponse::Ok().json(json_response);
-        },
+    let purchase = match query_result {
+        Ok(purchase) => purchase,
         Err(err) => {
             let json_error = json!({
                 "status": "error",
@@ -121,20 +269,39 @@ async fn get_purchase(data: web::Data<AppState>, path: web::Path<PathOptions>) -
             });
             return HttpResponse::InternalServerError().json(json_error);
         }
-    }
+    };
+
+    let etag = etag::entity_tag(purchase.updated_at);
+    let json_response = json!({
+        "status": "success",
+        "data": purchase
+    });
+
+    HttpResponse::Ok().insert_header(ETag(etag)).json(json_response)
 }
 
 
-#[patch("/{id}")]
-async fn update_purchase(data: web::Data<AppState>, path: web::Path<PathOptions>, body: web::Json<UpdatePurchase>) -> impl Responder {
+#[patch("/{id}", guard = "patching::is_json_patch")]
+async fn json_patch_purchase(data: web::Data<AppState>, audit: AuditContext, path: web::Path<PathOptions>, if_match: Option<web::Header<IfMatch>>, body: web::Json<JsonPatch>) -> impl Responder {
     let purchase_id = path.into_inner().id;
 
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
     let query_result = sqlx::query_as!(
         PurchaseModel,
-        "SELECT * FROM purchases WHERE id = $1",
+        "SELECT * FROM purchases WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
         purchase_id
     )
-        .fetch_one(&data.db)
+        .fetch_one(&mut tx)
         .await;
 
     let purchase = match query_result {
@@ -147,71 +314,756 @@ async fn update_purchase(data: web::Data<AppState>, path: web::Path<PathOptions>
             return HttpResponse::InternalServerError().json(json_error);
         }
     };
+    let before = audit::snapshot(&purchase);
+    let previous_product_id = purchase.product_id;
+    let previous_quantity = purchase.quantity;
+
+    if !etag::if_match(&if_match, &etag::entity_tag(purchase.updated_at)) {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("Purchase {} has been modified since it was fetched", purchase_id)
+        });
+        return HttpResponse::PreconditionFailed().json(json_error);
+    }
+
+    let purchase: PurchaseModel = match patching::apply(&purchase, &body, &IMMUTABLE_FIELDS) {
+        Ok(purchase) => purchase,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::build(err.status()).json(json_error);
+        }
+    };
+
+    if purchase.quantity < 1 {
+        let json_error = json!({
+            "status": "error",
+            "message": "quantity must be at least 1"
+        });
+        return HttpResponse::BadRequest().json(json_error);
+    }
+
+    // Moving a purchase to another product re-prices it as of the purchase date.
+    let unit_price = if purchase.product_id != previous_product_id {
+        let purchased_at = purchase.created_at.unwrap_or_else(Utc::now);
+        match pricing::price_as_of(&mut tx, purchase.product_id, purchased_at).await {
+            Ok(Some(price)) => price,
+            Ok(None) => {
+                let json_error = json!({
+                    "status": "error",
+                    "message": format!("Product {} had no price at {}", purchase.product_id, purchased_at)
+                });
+                return HttpResponse::UnprocessableEntity().json(json_error);
+            },
+            Err(err) => {
+                let json_error = json!({
+                    "status": "error",
+                    "message": format!("{}", err)
+                });
+                return HttpResponse::InternalServerError().json(json_error);
+            }
+        }
+    } else {
+        purchase.unit_price.clone()
+    };
+
+    // Cancelled purchases have already returned their units to stock.
+    let holds_stock = purchase.status != PurchaseStatus::Cancelled.as_str();
+    let mut warehouse_id = purchase.warehouse_id;
+    if holds_stock && (purchase.product_id != previous_product_id || purchase.quantity != previous_quantity) {
+        let previous = (previous_product_id, previous_quantity);
+        let next = (purchase.product_id, purchase.quantity);
+        match inventory::rebook(&mut tx, purchase.warehouse_id, previous, next, data.config.fulfilment_strategy).await {
+            Ok(Some(picked)) => warehouse_id = picked,
+            Ok(None) => {
+                let available = inventory::on_hand(&mut tx, purchase.product_id).await.ok().flatten().unwrap_or(0);
+                return out_of_stock(purchase.product_id, purchase.quantity, available);
+            },
+            Err(err) => {
+                let json_error = json!({
+                    "status": "error",
+                    "message": format!("{}", err)
+                });
+                return HttpResponse::InternalServerError().json(json_error);
+            }
+        }
+    }
 
     let now = Utc::now();
 
     let query_result = sqlx::query_as!(
         PurchaseModel,
-        "UPDATE purchases SET product_id = $1, user_id = $2, updated_at = $3 WHERE id = $4 RETURNING *",
-        body.product_id.to_owned().unwrap_or(purchase.product_id),
-        body.user_id.to_owned().unwrap_or(purchase.user_id),
+        "UPDATE purchases SET product_id = $1, user_id = $2, quantity = $3, unit_price = $4, warehouse_id = $5, updated_at = $6 WHERE id = $7 RETURNING *",
+        purchase.product_id,
+        purchase.user_id,
+        purchase.quantity,
+        unit_price,
+        warehouse_id,
         now,
         purchase_id
     )
-        .fetch_one(&data.db)
+        .fetch_one(&mut tx)
         .await;
 
-    match query_result {
-        Ok(purchase) => {
-            let json_response = json!({
-                "status": "success",
-                "data": purchase
-            });
-            return HttpResponse::Ok().json(json_response);
-        },
+    let purchase = match query_result {
+        Ok(purchase) => purchase,
         Err(err) => {
             let json_error = json!({
                 "status": "error",
                 "message": format!("{}", err)
             });
-
-            return HttpResponse::InternalServerError().json(json_error)
+            return HttpResponse::InternalServerError().json(json_error);
         }
+    };
+
+    let query_result = audit::record(&mut tx, &audit, "update", "purchases", purchase_id, before, audit::snapshot(&purchase)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
     }
+
+    let json_response = json!({
+        "status": "success",
+        "data": purchase
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(purchase.updated_at))).json(json_response)
 }
 
-#[delete("/{id}")]
-async fn delete_purchase(data: web::Data<AppState>, path: web::Path<PathOptions>) -> impl Responder {
+#[patch("/{id}")]
+async fn update_purchase(data: web::Data<AppState>, audit: AuditContext, path: web::Path<PathOptions>, if_match: Option<web::Header<IfMatch>>, body: web::Json<UpdatePurchase>) -> impl Responder {
     let purchase_id = path.into_inner().id;
 
-    let query_result = sqlx::query!("DELETE FROM purchases WHERE id = $1", purchase_id)
-        .execute(&data.db)
-        .await
-        .unwrap()
-        .rows_affected();
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        PurchaseModel,
+        "SELECT * FROM purchases WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+        purchase_id
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let purchase = match query_result {
+        Ok(purchase) => purchase,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+    let before = audit::snapshot(&purchase);
+    let previous_product_id = purchase.product_id;
+    let previous_quantity = purchase.quantity;
+
+    if !etag::if_match(&if_match, &etag::entity_tag(purchase.updated_at)) {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("Purchase {} has been modified since it was fetched", purchase_id)
+        });
+        return HttpResponse::PreconditionFailed().json(json_error);
+    }
+
+    let purchase = match body.into_inner().merge_into(purchase) {
+        Ok(purchase) => purchase,
+        Err(message) => {
+            let json_error = json!({
+                "status": "error",
+                "message": message
+            });
+            return HttpResponse::BadRequest().json(json_error);
+        }
+    };
 
-    if query_result == 0 {
+    if purchase.quantity < 1 {
         let json_error = json!({
             "status": "error",
-            "message": format!("No results found with ID: {}", purchase_id)
+            "message": "quantity must be at least 1"
         });
-        return HttpResponse::InternalServerError().json(json_error)
+        return HttpResponse::BadRequest().json(json_error);
+    }
+
+    // Moving a purchase to another product re-prices it as of the purchase date.
+    let unit_price = if purchase.product_id != previous_product_id {
+        let purchased_at = purchase.created_at.unwrap_or_else(Utc::now);
+        match pricing::price_as_of(&mut tx, purchase.product_id, purchased_at).await {
+            Ok(Some(price)) => price,
+            Ok(None) => {
+                let json_error = json!({
+                    "status": "error",
+                    "message": format!("Product {} had no price at {}", purchase.product_id, purchased_at)
+                });
+                return HttpResponse::UnprocessableEntity().json(json_error);
+            },
+            Err(err) => {
+                let json_error = json!({
+                    "status": "error",
+                    "message": format!("{}", err)
+                });
+                return HttpResponse::InternalServerError().json(json_error);
+            }
+        }
+    } else {
+        purchase.unit_price.clone()
+    };
+
+    // Cancelled purchases have already returned their units to stock.
+    let holds_stock = purchase.status != PurchaseStatus::Cancelled.as_str();
+    let mut warehouse_id = purchase.warehouse_id;
+    if holds_stock && (purchase.product_id != previous_product_id || purchase.quantity != previous_quantity) {
+        let previous = (previous_product_id, previous_quantity);
+        let next = (purchase.product_id, purchase.quantity);
+        match inventory::rebook(&mut tx, purchase.warehouse_id, previous, next, data.config.fulfilment_strategy).await {
+            Ok(Some(picked)) => warehouse_id = picked,
+            Ok(None) => {
+                let available = inventory::on_hand(&mut tx, purchase.product_id).await.ok().flatten().unwrap_or(0);
+                return out_of_stock(purchase.product_id, purchase.quantity, available);
+            },
+            Err(err) => {
+                let json_error = json!({
+                    "status": "error",
+                    "message": format!("{}", err)
+                });
+                return HttpResponse::InternalServerError().json(json_error);
+            }
+        }
+    }
+
+    let now = Utc::now();
+
+    let query_result = sqlx::query_as!(
+        PurchaseModel,
+        "UPDATE purchases SET product_id = $1, user_id = $2, quantity = $3, unit_price = $4, warehouse_id = $5, updated_at = $6 WHERE id = $7 RETURNING *",
+        purchase.product_id,
+        purchase.user_id,
+        purchase.quantity,
+        unit_price,
+        warehouse_id,
+        now,
+        purchase_id
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let purchase = match query_result {
+        Ok(purchase) => purchase,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
     };
 
+    let query_result = audit::record(&mut tx, &audit, "update", "purchases", purchase_id, before, audit::snapshot(&purchase)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
     let json_response = json!({
         "status": "success",
-        "message": "successfully deleted"
+        "data": purchase
     });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(purchase.updated_at))).json(json_response)
+}
 
-    HttpResponse::Ok().json(json_response)
+#[delete("/{id}")]
+async fn delete_purchase(data: web::Data<AppState>, audit: AuditContext, path: web::Path<PathOptions>, if_match: Option<web::Header<IfMatch>>) -> impl Responder {
+    let purchase_id = path.into_inner().id;
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        PurchaseModel,
+        "SELECT * FROM purchases WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+        purchase_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let purchase = match query_result {
+        Ok(purchase) => purchase,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    if let Some(purchase) = &purchase {
+        if !etag::if_match(&if_match, &etag::entity_tag(purchase.updated_at)) {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("Purchase {} has been modified since it was fetched", purchase_id)
+            });
+            return HttpResponse::PreconditionFailed().json(json_error);
+        }
+    }
+
+    let now = Utc::now();
+
+    let query_result = sqlx::query!(
+        "UPDATE purchases SET deleted_at = $1, updated_at = $1 WHERE id = $2 AND deleted_at IS NULL",
+        now,
+        purchase_id
+    )
+        .execute(&mut tx)
+        .await;
+
+    let rows_affected = match query_result {
+        Ok(result) => result.rows_affected(),
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    if rows_affected == 0 {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("No results found with ID: {}", purchase_id)
+        });
+        return HttpResponse::InternalServerError().json(json_error)
+    };
+
+    if let Some(purchase) = &purchase {
+        if let Err(err) = inventory::release_purchase(&mut tx, &purchase.status, purchase.warehouse_id, purchase.product_id, purchase.quantity).await {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    let query_result = audit::record(&mut tx, &audit, "delete", "purchases", purchase_id, purchase.as_ref().and_then(audit::snapshot), None).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "message": "successfully deleted"
+    });
+
+    HttpResponse::Ok().json(json_response)
+}
+
+#[get("/trash")]
+async fn get_deleted_purchases(data: web::Data<AppState>, _admin: Admin, opts: web::Query<FilterOptions>) -> impl Responder {
+    let limit = opts.limit.unwrap_or(10);
+    let offset = opts.offset.unwrap_or(1).saturating_sub(1) * limit;
+
+    let query_result = sqlx::query_as!(
+        PurchaseModel,
+        "SELECT * FROM purchases WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC LIMIT $1 OFFSET $2",
+        limit as i32,
+        offset as i32
+    )
+        .fetch_all(&data.db)
+        .await;
+
+    let purchases = match query_result {
+        Ok(purchases) => purchases,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_count = sqlx::query_scalar("SELECT COUNT(*) FROM purchases WHERE deleted_at IS NOT NULL")
+        .fetch_one(&data.db)
+        .await;
+
+    let deleted_count: i64 = match query_count {
+        Ok(count) => count,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+    let json_response = json!({
+        "status": "success",
+        "count": deleted_count,
+        "data": purchases
+    });
+
+    HttpResponse::Ok().json(json_response)
+}
+
+#[post("/{id}/restore")]
+async fn restore_purchase(data: web::Data<AppState>, _admin: Admin, audit: AuditContext, path: web::Path<PathOptions>) -> impl Responder {
+    let purchase_id = path.into_inner().id;
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        PurchaseModel,
+        "SELECT * FROM purchases WHERE id = $1 AND deleted_at IS NOT NULL FOR UPDATE",
+        purchase_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let deleted = match query_result {
+        Ok(Some(purchase)) => purchase,
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no deleted purchase with ID: {}", purchase_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+    let before = audit::snapshot(&deleted);
+
+    // Deleting returned the units to stock, so a restored purchase takes them
+    // again, from its old warehouse if it still has enough.
+    let mut warehouse_id = deleted.warehouse_id;
+    if deleted.status != PurchaseStatus::Cancelled.as_str() {
+        let query_result = match inventory::take(&mut tx, deleted.warehouse_id, deleted.product_id, deleted.quantity).await {
+            Ok(Some(_)) => Ok(Some(deleted.warehouse_id)),
+            Ok(None) => inventory::fulfil(&mut tx, deleted.product_id, deleted.quantity, data.config.fulfilment_strategy, None).await,
+            Err(err) => Err(err)
+        };
+
+        match query_result {
+            Ok(Some(picked)) => warehouse_id = picked,
+            Ok(None) => {
+                let available = inventory::on_hand(&mut tx, deleted.product_id).await.ok().flatten().unwrap_or(0);
+                return out_of_stock(deleted.product_id, deleted.quantity, available);
+            },
+            Err(err) => {
+                let json_error = json!({
+                    "status": "error",
+                    "message": format!("{}", err)
+                });
+                return HttpResponse::InternalServerError().json(json_error);
+            }
+        }
+    }
+
+    let now = Utc::now();
+
+    let query_result = sqlx::query_as!(
+        PurchaseModel,
+        "UPDATE purchases SET deleted_at = NULL, warehouse_id = $1, updated_at = $2 WHERE id = $3 RETURNING *",
+        warehouse_id,
+        now,
+        purchase_id
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let purchase = match query_result {
+        Ok(purchase) => purchase,
+        Err(sqlx::Error::Database(err)) if err.code().as_deref() == Some("23505") => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("Cannot restore purchase {}: {}", purchase_id, err)
+            });
+            return HttpResponse::Conflict().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = audit::record(&mut tx, &audit, "restore", "purchases", purchase_id, before, audit::snapshot(&purchase)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": purchase
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(purchase.updated_at))).json(json_response)
+}
+
+/// Admin only: `X-User-Id` is not authenticated, so it cannot prove that the
+/// caller owns the purchase, not even to cancel it.
+#[post("/{id}/transitions")]
+async fn create_transition(data: web::Data<AppState>, _admin: Admin, audit: AuditContext, path: web::Path<PathOptions>, body: web::Json<CreatePurchaseTransition>) -> impl Responder {
+    let purchase_id = path.into_inner().id;
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        PurchaseModel,
+        "SELECT * FROM purchases WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+        purchase_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let purchase = match query_result {
+        Ok(Some(purchase)) => purchase,
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no purchase with ID: {}", purchase_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+    let before = audit::snapshot(&purchase);
+
+    let current = PurchaseStatus::parse(&purchase.status);
+    let allowed = current.map(|current| current.can_transition_to(body.status)).unwrap_or(false);
+    if !allowed {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("Purchase {} cannot move from {} to {}", purchase_id, purchase.status, body.status.as_str())
+        });
+        return HttpResponse::Conflict().json(json_error);
+    }
+
+    if body.status == PurchaseStatus::Cancelled {
+        if let Err(err) = inventory::release(&mut tx, purchase.warehouse_id, purchase.product_id, purchase.quantity).await {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    let now = Utc::now();
+
+    let query_result = sqlx::query_as!(
+        PurchaseModel,
+        "UPDATE purchases SET status = $1, updated_at = $2 WHERE id = $3 RETURNING *",
+        body.status.as_str(),
+        now,
+        purchase_id
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let purchase = match query_result {
+        Ok(purchase) => purchase,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query!(
+        "INSERT INTO purchase_status_transitions (purchase_id, from_status, to_status, actor, reason, created_at) VALUES ($1, $2, $3, $4, $5, $6)",
+        purchase_id,
+        current.map(|current| current.as_str()),
+        body.status.as_str(),
+        audit.actor,
+        body.reason,
+        now
+    )
+        .execute(&mut tx)
+        .await;
+
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let query_result = audit::record(&mut tx, &audit, "update", "purchases", purchase_id, before, audit::snapshot(&purchase)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": purchase
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(purchase.updated_at))).json(json_response)
+}
+
+#[get("/{id}/transitions")]
+async fn get_transitions(data: web::Data<AppState>, path: web::Path<PathOptions>) -> impl Responder {
+    let purchase_id = path.into_inner().id;
+
+    let query_result = sqlx::query_as!(
+        PurchaseTransitionModel,
+        "SELECT * FROM purchase_status_transitions WHERE purchase_id = $1 ORDER BY created_at",
+        purchase_id
+    )
+        .fetch_all(&data.db)
+        .await;
+
+    let transitions = match query_result {
+        Ok(transitions) => transitions,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let json_response = json!({
+        "status": "success",
+        "count": transitions.len(),
+        "data": transitions
+    });
+
+    HttpResponse::Ok().json(json_response)
+}
+
+/// 409 for a purchase that no single warehouse can fulfil.
+fn out_of_stock(product_id: Uuid, requested: i32, available: i32) -> HttpResponse {
+    let json_error = json!({
+        "status": "error",
+        "message": format!("Product {} is out of stock: requested {}, available {} across all warehouses", product_id, requested, available)
+    });
+    HttpResponse::Conflict().json(json_error)
 }
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     let scope = web::scope("/purchases")
         .service(get_purchases)
+        .service(get_deleted_purchases)
         .service(create_purchase)
         .service(get_purchase)
+        .service(create_transition)
+        .service(get_transitions)
+        .service(json_patch_purchase)
         .service(update_purchase)
-        .service(delete_purchase);
+        .service(delete_purchase)
+        .service(restore_purchase);
     cfg.service(scope);
 }
\ No newline at end of file
@@ -0,0 +1,686 @@
+use actix_web::{get, post, HttpResponse, Responder, web, http::header::ETag};
+use serde_json::json;
+
+use crate::{AppState, audit::{self, AuditContext}, auth::{Admin, CurrentUser}, etag, moderation, models::questions::{QuestionModel, AnswerModel, QuestionThread, CreatePost}, schema::{FilterOptions, PathOptions}};
+
+/// Questions on a product, most upvoted first, each with its answers. Staff
+/// and verified-buyer answers come first. Registered under the `/products`
+/// scope.
+#[get("/{id}/questions")]
+pub async fn get_product_questions(data: web::Data<AppState>, path: web::Path<PathOptions>, opts: web::Query<FilterOptions>) -> impl Responder {
+    let product_id = path.into_inner().id;
+    let limit = opts.limit.unwrap_or(10);
+    let offset = opts.offset.unwrap_or(1).saturating_sub(1) * limit;
+
+    let query_result = sqlx::query_scalar!(
+        "SELECT id FROM products WHERE id = $1 AND deleted_at IS NULL",
+        product_id
+    )
+        .fetch_optional(&data.db)
+        .await;
+
+    match query_result {
+        Ok(Some(_)) => {},
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no product with ID: {}", product_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    let query_result = sqlx::query_as!(
+        QuestionModel,
+        "SELECT * FROM product_questions WHERE product_id = $1 ORDER BY upvotes DESC, created_at LIMIT $2 OFFSET $3",
+        product_id,
+        limit as i32,
+        offset as i32
+    )
+        .fetch_all(&data.db)
+        .await;
+
+    let questions = match query_result {
+        Ok(questions) => questions,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_count = sqlx::query_scalar("SELECT COUNT(*) FROM product_questions WHERE product_id = $1")
+        .bind(product_id)
+        .fetch_one(&data.db)
+        .await;
+
+    let question_count: i64 = match query_count {
+        Ok(count) => count,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let ids: Vec<_> = questions.iter().map(|question| question.id).collect();
+    let query_result = sqlx::query_as!(
+        AnswerModel,
+        "SELECT * FROM product_answers WHERE question_id = ANY($1) ORDER BY staff DESC, verified_buyer DESC, upvotes DESC, created_at",
+        &ids
+    )
+        .fetch_all(&data.db)
+        .await;
+
+    let mut answers = match query_result {
+        Ok(answers) => answers,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let threads: Vec<QuestionThread> = questions
+        .into_iter()
+        .map(|question| {
+            let (own, rest) = answers.drain(..).partition(|answer| answer.question_id == question.id);
+            answers = rest;
+            QuestionThread { question, answers: own }
+        })
+        .collect();
+
+    let json_response = json!({
+        "status": "success",
+        "count": question_count,
+        "data": threads
+    });
+    HttpResponse::Ok().json(json_response)
+}
+
+/// Registered under the `/products` scope.
+#[post("/{id}/questions")]
+pub async fn ask_question(data: web::Data<AppState>, audit: AuditContext, user: CurrentUser, path: web::Path<PathOptions>, body: web::Json<CreatePost>) -> impl Responder {
+    let product_id = path.into_inner().id;
+    let CurrentUser(user_id) = user;
+
+    let text = body.body.trim();
+    if text.is_empty() {
+        let json_error = json!({
+            "status": "error",
+            "message": "body must not be empty"
+        });
+        return HttpResponse::BadRequest().json(json_error);
+    }
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_scalar!(
+        "SELECT id FROM products WHERE id = $1 AND deleted_at IS NULL FOR SHARE",
+        product_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    match query_result {
+        Ok(Some(_)) => {},
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no product with ID: {}", product_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    let query_result = sqlx::query_scalar!(
+        "SELECT id FROM users WHERE id = $1 AND deleted_at IS NULL FOR SHARE",
+        user_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    match query_result {
+        Ok(Some(_)) => {},
+        Ok(None) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("There is no user with ID: {}", user_id)
+            });
+            return HttpResponse::UnprocessableEntity().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    let query_result = sqlx::query_as!(
+        QuestionModel,
+        "INSERT INTO product_questions (product_id, user_id, body) VALUES ($1, $2, $3) RETURNING *",
+        product_id,
+        user_id,
+        text
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let question = match query_result {
+        Ok(question) => question,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = audit::record(&mut tx, &audit, "create", "product_questions", question.id, None, audit::snapshot(&question)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": question
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(question.updated_at))).json(json_response)
+}
+
+#[get("/{id}")]
+async fn get_question(data: web::Data<AppState>, path: web::Path<PathOptions>) -> impl Responder {
+    let question_id = path.into_inner().id;
+
+    let query_result = sqlx::query_as!(
+        QuestionModel,
+        "SELECT q.* FROM product_questions q JOIN products p ON p.id = q.product_id AND p.deleted_at IS NULL WHERE q.id = $1",
+        question_id
+    )
+        .fetch_optional(&data.db)
+        .await;
+
+    let question = match query_result {
+        Ok(Some(question)) => question,
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no question with ID: {}", question_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        AnswerModel,
+        "SELECT * FROM product_answers WHERE question_id = $1 ORDER BY staff DESC, verified_buyer DESC, upvotes DESC, created_at",
+        question_id
+    )
+        .fetch_all(&data.db)
+        .await;
+
+    match query_result {
+        Ok(answers) => {
+            let json_response = json!({
+                "status": "success",
+                "data": QuestionThread { question, answers }
+            });
+            HttpResponse::Ok().json(json_response)
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            HttpResponse::InternalServerError().json(json_error)
+        }
+    }
+}
+
+/// Answers posted with admin credentials are marked as coming from staff.
+#[post("/{id}/answers")]
+async fn answer_question(data: web::Data<AppState>, admin: Option<Admin>, audit: AuditContext, user: CurrentUser, path: web::Path<PathOptions>, body: web::Json<CreatePost>) -> impl Responder {
+    let question_id = path.into_inner().id;
+    let CurrentUser(user_id) = user;
+
+    let text = body.body.trim();
+    if text.is_empty() {
+        let json_error = json!({
+            "status": "error",
+            "message": "body must not be empty"
+        });
+        return HttpResponse::BadRequest().json(json_error);
+    }
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_scalar!(
+        "SELECT q.product_id FROM product_questions q JOIN products p ON p.id = q.product_id AND p.deleted_at IS NULL
+            WHERE q.id = $1
+            FOR SHARE OF q",
+        question_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let product_id = match query_result {
+        Ok(Some(product_id)) => product_id,
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no question with ID: {}", question_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_scalar!(
+        "SELECT id FROM users WHERE id = $1 AND deleted_at IS NULL FOR SHARE",
+        user_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    match query_result {
+        Ok(Some(_)) => {},
+        Ok(None) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("There is no user with ID: {}", user_id)
+            });
+            return HttpResponse::UnprocessableEntity().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    let verified_buyer = match moderation::has_purchased(&mut tx, user_id, product_id).await {
+        Ok(verified_buyer) => verified_buyer,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        AnswerModel,
+        "INSERT INTO product_answers (question_id, user_id, body, verified_buyer, staff) VALUES ($1, $2, $3, $4, $5) RETURNING *",
+        question_id,
+        user_id,
+        text,
+        verified_buyer,
+        admin.is_some()
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let answer = match query_result {
+        Ok(answer) => answer,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = audit::record(&mut tx, &audit, "create", "product_answers", answer.id, None, audit::snapshot(&answer)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": answer
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(answer.updated_at))).json(json_response)
+}
+
+/// Adds the caller's upvote; repeating it has no further effect.
+#[post("/{id}/upvotes")]
+async fn upvote_question(data: web::Data<AppState>, user: CurrentUser, path: web::Path<PathOptions>) -> impl Responder {
+    let question_id = path.into_inner().id;
+    let CurrentUser(user_id) = user;
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        QuestionModel,
+        "SELECT q.* FROM product_questions q JOIN products p ON p.id = q.product_id AND p.deleted_at IS NULL
+            WHERE q.id = $1
+            FOR UPDATE OF q",
+        question_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let question = match query_result {
+        Ok(Some(question)) => question,
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no question with ID: {}", question_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_scalar!(
+        "SELECT id FROM users WHERE id = $1 AND deleted_at IS NULL FOR SHARE",
+        user_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    match query_result {
+        Ok(Some(_)) => {},
+        Ok(None) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("There is no user with ID: {}", user_id)
+            });
+            return HttpResponse::UnprocessableEntity().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    let query_result = sqlx::query!(
+        "INSERT INTO product_question_votes (question_id, user_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        question_id,
+        user_id
+    )
+        .execute(&mut tx)
+        .await;
+
+    let question = match query_result {
+        Ok(result) if result.rows_affected() == 0 => question,
+        Ok(_) => {
+            let query_result = sqlx::query_as!(
+                QuestionModel,
+                "UPDATE product_questions SET upvotes = upvotes + 1 WHERE id = $1 RETURNING *",
+                question_id
+            )
+                .fetch_one(&mut tx)
+                .await;
+
+            match query_result {
+                Ok(question) => question,
+                Err(err) => {
+                    let json_error = json!({
+                        "status": "error",
+                        "message": format!("{}", err)
+                    });
+                    return HttpResponse::InternalServerError().json(json_error);
+                }
+            }
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": question
+    });
+    HttpResponse::Ok().json(json_response)
+}
+
+/// Adds the caller's upvote; repeating it has no further effect.
+#[post("/{id}/upvotes")]
+async fn upvote_answer(data: web::Data<AppState>, user: CurrentUser, path: web::Path<PathOptions>) -> impl Responder {
+    let answer_id = path.into_inner().id;
+    let CurrentUser(user_id) = user;
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        AnswerModel,
+        "SELECT a.* FROM product_answers a
+            JOIN product_questions q ON q.id = a.question_id
+            JOIN products p ON p.id = q.product_id AND p.deleted_at IS NULL
+            WHERE a.id = $1
+            FOR UPDATE OF a",
+        answer_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let answer = match query_result {
+        Ok(Some(answer)) => answer,
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no answer with ID: {}", answer_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_scalar!(
+        "SELECT id FROM users WHERE id = $1 AND deleted_at IS NULL FOR SHARE",
+        user_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    match query_result {
+        Ok(Some(_)) => {},
+        Ok(None) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("There is no user with ID: {}", user_id)
+            });
+            return HttpResponse::UnprocessableEntity().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    let query_result = sqlx::query!(
+        "INSERT INTO product_answer_votes (answer_id, user_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        answer_id,
+        user_id
+    )
+        .execute(&mut tx)
+        .await;
+
+    let answer = match query_result {
+        Ok(result) if result.rows_affected() == 0 => answer,
+        Ok(_) => {
+            let query_result = sqlx::query_as!(
+                AnswerModel,
+                "UPDATE product_answers SET upvotes = upvotes + 1 WHERE id = $1 RETURNING *",
+                answer_id
+            )
+                .fetch_one(&mut tx)
+                .await;
+
+            match query_result {
+                Ok(answer) => answer,
+                Err(err) => {
+                    let json_error = json!({
+                        "status": "error",
+                        "message": format!("{}", err)
+                    });
+                    return HttpResponse::InternalServerError().json(json_error);
+                }
+            }
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": answer
+    });
+    HttpResponse::Ok().json(json_response)
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    let questions = web::scope("/questions")
+        .service(get_question)
+        .service(answer_question)
+        .service(upvote_question);
+    let answers = web::scope("/answers")
+        .service(upvote_answer);
+
+    cfg.service(questions).service(answers);
+}
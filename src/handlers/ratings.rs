@@ -1,21 +1,58 @@
-use actix_web::{get, post, patch, delete, HttpResponse, Responder, web};
+use actix_web::{get, post, put, patch, delete, HttpResponse, Responder, web, http::header::{ETag, IfMatch}};
 use chrono::Utc;
 use serde_json::json;
+use json_patch::Patch as JsonPatch;
 
-use crate::{AppState, models::ratings::{RatingFilterOptions, RatingModel, CreateRating, UpdateRating}, schema::PathOptions};
+use crate::{AppState, audit::{self, AuditContext}, auth::{Admin, CurrentUser}, etag, moderation::{self, PurchasePolicy}, patching, models::ratings::{RatingFilterOptions, RatingModel, CreateRating, UpdateRating, PutRating, ModerateRating, ModerationStatus, RatingSort, RatingVoteModel, CastVote, Vote, RatingSummary, AbuseClusterModel}, schema::{DeletedOptions, FilterOptions, PathOptions}};
+
+/// A rating always belongs to the user and product it was created for, and
+/// only the server decides whether it is a verified purchase or approved.
+/// Vote counts change through `POST /ratings/{id}/votes`.
+const IMMUTABLE_FIELDS: [&str; 14] = [
+    "id", "created_at", "updated_at", "deleted_at", "product_id", "user_id", "verified_purchase",
+    "moderation_status", "moderation_reason", "moderated_at", "helpful_count", "unhelpful_count", "helpful_score",
+    "abuse_flags"
+];
 
 #[get("")]
-async fn get_ratings(data: web::Data<AppState>, opts: web::Query<RatingFilterOptions>) -> impl Responder {
+async fn get_ratings(data: web::Data<AppState>, admin: Option<Admin>, opts: web::Query<RatingFilterOptions>) -> impl Responder {
     let limit = opts.limit.unwrap_or(10);
-    let offset = (opts.offset.unwrap_or(1) - 1) * limit;
+    let offset = opts.offset.unwrap_or(1).saturating_sub(1) * limit;
     let product_id = opts.product_id;
     let user_id = opts.user_id;
 
+    let include_deleted = opts.include_deleted.unwrap_or(false);
+    if include_deleted && admin.is_none() {
+        let json_error = json!({
+            "status": "error",
+            "message": "include_deleted requires admin credentials"
+        });
+        return HttpResponse::Forbidden().json(json_error);
+    }
+
+    // Reviews that have not been approved are only visible to admins.
+    let moderation_status = match (&admin, opts.moderation_status) {
+        (Some(_), status) => status.map(|status| status.as_str()),
+        (None, None) => Some(ModerationStatus::Approved.as_str()),
+        (None, Some(_)) => {
+            let json_error = json!({
+                "status": "error",
+                "message": "moderation_status requires admin credentials"
+            });
+            return HttpResponse::Forbidden().json(json_error);
+        }
+    };
+
     let query_result = sqlx::query_as!(
         RatingModel,
-        "SELECT * FROM ratings WHERE product_id = COALESCE($1, product_id) AND user_id = COALESCE($2, user_id) ORDER BY created_at LIMIT $3 OFFSET $4",
+        "SELECT * FROM ratings WHERE product_id = COALESCE($1, product_id) AND user_id = COALESCE($2, user_id) AND (deleted_at IS NULL OR $3) AND verified_purchase = COALESCE($4, verified_purchase) AND moderation_status = COALESCE($5, moderation_status)
+            ORDER BY CASE WHEN $6 THEN helpful_score END DESC NULLS LAST, created_at LIMIT $7 OFFSET $8",
         product_id,
         user_id,
+        include_deleted,
+        opts.verified_purchase,
+        moderation_status,
+        opts.sort == Some(RatingSort::Helpful),
         limit as i32, 
         offset as i32
     )
@@ -23,32 +60,8 @@ async fn get_ratings(data: web::Data<AppState>, opts: web::Query<RatingFilterOpt
         .await;
 
 
-    match query_result {
-        Ok(ratings) => {
-            let query_count = sqlx::query_scalar("SELECT COUNT(*) FROM ratings WHERE product_id = COALESCE($1, product_id) AND user_id = COALESCE($2, user_id)")
-                .bind(product_id)
-                .bind(user_id)
-                .fetch_one(&data.db)
-                .await;
-
-            let rating_count: i64 = match query_count {
-                Ok(count) => count,
-                Err(err) => {
-                    let json_error = json!({
-                        "status": "error",
-                        "data": format!("{}", err)
-                    });
-                    return HttpResponse::InternalServerError().json(json_error);
-                }
-            };
-            let json_response = json!({
-                "status": "success",
-                "count": rating_count,
-                "data": ratings
-            });
-
-            return HttpResponse::Ok().json(json_response);
-        },
+    let ratings = match query_result {
+        Ok(ratings) => ratings,
         Err(err) => {
             let json_error = json!({
                 "status": "error",
@@ -58,63 +71,188 @@ async fn get_ratings(data: web::Data<AppState>, opts: web::Query<RatingFilterOpt
             return HttpResponse::InternalServerError().json(json_error);
             
         }
-    }
+    };
+
+    let query_count = sqlx::query_scalar("SELECT COUNT(*) FROM ratings WHERE product_id = COALESCE($1, product_id) AND user_id = COALESCE($2, user_id) AND (deleted_at IS NULL OR $3) AND verified_purchase = COALESCE($4, verified_purchase) AND moderation_status = COALESCE($5, moderation_status)")
+        .bind(product_id)
+        .bind(user_id)
+        .bind(include_deleted)
+        .bind(opts.verified_purchase)
+        .bind(moderation_status)
+        .fetch_one(&data.db)
+        .await;
+
+    let rating_count: i64 = match query_count {
+        Ok(count) => count,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "data": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+    let json_response = json!({
+        "status": "success",
+        "count": rating_count,
+        "data": ratings
+    });
+
+    HttpResponse::Ok().json(json_response)
 }
 
 
 #[post("")]
-async fn create_rating(data: web::Data<AppState>, body: web::Json<CreateRating>) -> impl Responder {
+async fn create_rating(data: web::Data<AppState>, audit: AuditContext, body: web::Json<CreateRating>) -> impl Responder {
+    if let Err(message) = moderation::validate_title(body.title.as_deref()) {
+        let json_error = json!({
+            "status": "error",
+            "message": message
+        });
+        return HttpResponse::BadRequest().json(json_error);
+    }
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let verified_purchase = match moderation::has_purchased(&mut tx, body.user_id, body.product_id).await {
+        Ok(verified_purchase) => verified_purchase,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+    if !verified_purchase && data.config.rating_purchase_policy == PurchasePolicy::Reject {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("Only customers who bought product {} can rate it", body.product_id)
+        });
+        return HttpResponse::Forbidden().json(json_error);
+    }
+
+    let abuse_flags = match moderation::abuse_flags(&mut tx, &data.config.abuse_rules, body.user_id, body.product_id).await {
+        Ok(abuse_flags) => abuse_flags,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let (moderation_status, moderation_reason) = moderation::review_status(
+        &data.config.banned_words,
+        ModerationStatus::Approved,
+        None,
+        body.title.as_deref(),
+        body.body.as_deref()
+    );
+    let (moderation_status, moderation_reason) = moderation::quarantine(&data.config.abuse_rules, &abuse_flags, moderation_status, moderation_reason);
+
     let query_result = sqlx::query_as!(
         RatingModel,
-        "INSERT INTO ratings (rating, product_id, user_id) VALUES ($1, $2, $3) RETURNING *",
+        "INSERT INTO ratings (rating, product_id, user_id, verified_purchase, title, body, moderation_status, moderation_reason, abuse_flags) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING *",
         body.rating,
         body.product_id,
-        body.user_id
+        body.user_id,
+        verified_purchase,
+        body.title,
+        body.body,
+        moderation_status.as_str(),
+        moderation_reason,
+        &abuse_flags
     )
-        .fetch_one(&data.db)
+        .fetch_one(&mut tx)
         .await;
 
-    match query_result {
-        Ok(rating) => {
-            let json_response = json!({
-                "status": "success",
-                "data": rating
+    let rating = match query_result {
+        Ok(rating) => rating,
+        Err(sqlx::Error::Database(err)) if err.code().as_deref() == Some("23505") => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("User {} has already rated product {}; use PUT /products/{}/ratings/me to change it", body.user_id, body.product_id, body.product_id)
             });
-
-            return HttpResponse::Ok().json(json_response);
+            return HttpResponse::Conflict().json(json_error);
         },
         Err(err) => {
             let json_error = json!({
                 "status": "error",
                 "message": format!("{}", err)
             });
-
             return HttpResponse::InternalServerError().json(json_error);
         }
+    };
+    let rating_id = rating.id;
+
+    let query_result = audit::record(&mut tx, &audit, "create", "ratings", rating_id, None, audit::snapshot(&rating)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
     }
+
+    let json_response = json!({
+        "status": "success",
+        "data": rating
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(rating.updated_at))).json(json_response)
 }
 
 #[get("/{id}")]
-async fn get_rating(data: web::Data<AppState>, path: web::Path<PathOptions>) -> impl Responder {
+async fn get_rating(data: web::Data<AppState>, admin: Option<Admin>, path: web::Path<PathOptions>, opts: web::Query<DeletedOptions>) -> impl Responder {
     let rating_id = path.into_inner().id;
 
+    let include_deleted = opts.include_deleted.unwrap_or(false);
+    if include_deleted && admin.is_none() {
+        let json_error = json!({
+            "status": "error",
+            "message": "include_deleted requires admin credentials"
+        });
+        return HttpResponse::Forbidden().json(json_error);
+    }
+
+    // Reviews that have not been approved are only visible to admins.
     let query_result = sqlx::query_as!(
         RatingModel,
-        "SELECT * FROM ratings WHERE id = $1",
-        rating_id
+        "SELECT * FROM ratings WHERE id = $1 AND (deleted_at IS NULL OR $2) AND (moderation_status = 'approved' OR $3)",
+        rating_id,
+        include_deleted,
+        admin.is_some()
     )
-        .fetch_one(&data.db)
+        .fetch_optional(&data.db)
         .await;
 
-    match query_result {
-        Ok(rating) => {
-            let json_response = json!({
-                "status": "success",
-                "data": rating
+    let rating = match query_result {
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no rating with ID: {}", rating_id)
             });
-
-            return HttpResponse::Ok().json(json_response);
+            return HttpResponse::NotFound().json(json_error);
         },
+        Ok(Some(rating)) => rating,
         Err(err) => {
             let json_error = json!({
                 "status": "error",
@@ -122,20 +260,39 @@ async fn get_rating(data: web::Data<AppState>, path: web::Path<PathOptions>) ->
             });
             return HttpResponse::InternalServerError().json(json_error);
         }
-    }
+    };
+
+    let etag = etag::entity_tag(rating.updated_at);
+    let json_response = json!({
+        "status": "success",
+        "data": rating
+    });
+
+    HttpResponse::Ok().insert_header(ETag(etag)).json(json_response)
 }
 
 
-#[patch("/{id}")]
-async fn update_rating(data: web::Data<AppState>, path: web::Path<PathOptions>, body: web::Json<UpdateRating>) -> impl Responder {
+#[patch("/{id}", guard = "patching::is_json_patch")]
+async fn json_patch_rating(data: web::Data<AppState>, audit: AuditContext, path: web::Path<PathOptions>, if_match: Option<web::Header<IfMatch>>, body: web::Json<JsonPatch>) -> impl Responder {
     let rating_id = path.into_inner().id;
 
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
     let query_result = sqlx::query_as!(
         RatingModel,
-        "SELECT * FROM ratings WHERE id = $1",
+        "SELECT * FROM ratings WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
         rating_id
     )
-        .fetch_one(&data.db)
+        .fetch_one(&mut tx)
         .await;
 
     let rating = match query_result {
@@ -148,72 +305,1292 @@ async fn update_rating(data: web::Data<AppState>, path: web::Path<PathOptions>,
             return HttpResponse::InternalServerError().json(json_error);
         }
     };
+    let before = audit::snapshot(&rating);
+
+    if !etag::if_match(&if_match, &etag::entity_tag(rating.updated_at)) {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("Rating {} has been modified since it was fetched", rating_id)
+        });
+        return HttpResponse::PreconditionFailed().json(json_error);
+    }
+
+    let text_before = (rating.title.clone(), rating.body.clone());
+
+    let rating: RatingModel = match patching::apply(&rating, &body, &IMMUTABLE_FIELDS) {
+        Ok(rating) => rating,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::build(err.status()).json(json_error);
+        }
+    };
+
+    if let Err(message) = moderation::validate_title(rating.title.as_deref()) {
+        let json_error = json!({
+            "status": "error",
+            "message": message
+        });
+        return HttpResponse::BadRequest().json(json_error);
+    }
+
+    // Only rewritten text goes back through moderation.
+    let (moderation_status, moderation_reason) = if (&rating.title, &rating.body) != (&text_before.0, &text_before.1) {
+        let current = ModerationStatus::parse(&rating.moderation_status).unwrap_or(ModerationStatus::Pending);
+        let (status, reason) = moderation::review_status(
+            &data.config.banned_words,
+            current,
+            rating.moderation_reason.clone(),
+            rating.title.as_deref(),
+            rating.body.as_deref()
+        );
+        (status.as_str().to_string(), reason)
+    } else {
+        (rating.moderation_status.clone(), rating.moderation_reason.clone())
+    };
 
     let now = Utc::now();
 
     let query_result = sqlx::query_as!(
         RatingModel,
-        "UPDATE ratings SET rating = $1, product_id = $2, user_id = $3, updated_at = $4 WHERE id = $5 RETURNING *",
-        body.rating.to_owned().unwrap_or(rating.rating),
-        body.product_id.to_owned().unwrap_or(rating.product_id),
-        body.user_id.to_owned().unwrap_or(rating.user_id),
+        "UPDATE ratings SET rating = $1, title = $2, body = $3, moderation_status = $4, moderation_reason = $5, updated_at = $6 WHERE id = $7 RETURNING *",
+        rating.rating,
+        rating.title,
+        rating.body,
+        moderation_status,
+        moderation_reason,
         now,
         rating_id
     )
-        .fetch_one(&data.db)
+        .fetch_one(&mut tx)
         .await;
 
-    match query_result {
-        Ok(rating) => {
-            let json_response = json!({
-                "status": "success",
-                "data": rating
-            });
-            return HttpResponse::Ok().json(json_response);
-        },
+    let rating = match query_result {
+        Ok(rating) => rating,
         Err(err) => {
             let json_error = json!({
                 "status": "error",
                 "message": format!("{}", err)
             });
-
-            return HttpResponse::InternalServerError().json(json_error)
+            return HttpResponse::InternalServerError().json(json_error);
         }
+    };
+
+    let query_result = audit::record(&mut tx, &audit, "update", "ratings", rating_id, before, audit::snapshot(&rating)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
     }
+
+    let json_response = json!({
+        "status": "success",
+        "data": rating
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(rating.updated_at))).json(json_response)
 }
 
-#[delete("/{id}")]
-async fn delete_rating(data: web::Data<AppState>, path: web::Path<PathOptions>) -> impl Responder {
+#[patch("/{id}")]
+async fn update_rating(data: web::Data<AppState>, audit: AuditContext, path: web::Path<PathOptions>, if_match: Option<web::Header<IfMatch>>, body: web::Json<UpdateRating>) -> impl Responder {
     let rating_id = path.into_inner().id;
 
-    let query_result = sqlx::query!("DELETE FROM ratings WHERE id = $1", rating_id)
-        .execute(&data.db)
-        .await
-        .unwrap()
-        .rows_affected();
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        RatingModel,
+        "SELECT * FROM ratings WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+        rating_id
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let rating = match query_result {
+        Ok(rating) => rating,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+    let before = audit::snapshot(&rating);
 
-    if query_result == 0 {
+    if !etag::if_match(&if_match, &etag::entity_tag(rating.updated_at)) {
         let json_error = json!({
             "status": "error",
-            "message": format!("No results found with ID: {}", rating_id)
+            "message": format!("Rating {} has been modified since it was fetched", rating_id)
         });
-        return HttpResponse::InternalServerError().json(json_error)
+        return HttpResponse::PreconditionFailed().json(json_error);
+    }
+
+    let text_before = (rating.title.clone(), rating.body.clone());
+
+    let rating = match body.into_inner().merge_into(rating) {
+        Ok(rating) => rating,
+        Err(message) => {
+            let json_error = json!({
+                "status": "error",
+                "message": message
+            });
+            return HttpResponse::BadRequest().json(json_error);
+        }
+    };
+
+    if let Err(message) = moderation::validate_title(rating.title.as_deref()) {
+        let json_error = json!({
+            "status": "error",
+            "message": message
+        });
+        return HttpResponse::BadRequest().json(json_error);
+    }
+
+    // Only rewritten text goes back through moderation.
+    let (moderation_status, moderation_reason) = if (&rating.title, &rating.body) != (&text_before.0, &text_before.1) {
+        let current = ModerationStatus::parse(&rating.moderation_status).unwrap_or(ModerationStatus::Pending);
+        let (status, reason) = moderation::review_status(
+            &data.config.banned_words,
+            current,
+            rating.moderation_reason.clone(),
+            rating.title.as_deref(),
+            rating.body.as_deref()
+        );
+        (status.as_str().to_string(), reason)
+    } else {
+        (rating.moderation_status.clone(), rating.moderation_reason.clone())
+    };
+
+    let now = Utc::now();
+
+    let query_result = sqlx::query_as!(
+        RatingModel,
+        "UPDATE ratings SET rating = $1, title = $2, body = $3, moderation_status = $4, moderation_reason = $5, updated_at = $6 WHERE id = $7 RETURNING *",
+        rating.rating,
+        rating.title,
+        rating.body,
+        moderation_status,
+        moderation_reason,
+        now,
+        rating_id
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let rating = match query_result {
+        Ok(rating) => rating,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
     };
 
+    let query_result = audit::record(&mut tx, &audit, "update", "ratings", rating_id, before, audit::snapshot(&rating)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
     let json_response = json!({
         "status": "success",
-        "message": "successfully deleted"
+        "data": rating
     });
-
-    HttpResponse::Ok().json(json_response)
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(rating.updated_at))).json(json_response)
+}
+
+#[delete("/{id}")]
+async fn delete_rating(data: web::Data<AppState>, audit: AuditContext, path: web::Path<PathOptions>, if_match: Option<web::Header<IfMatch>>) -> impl Responder {
+    let rating_id = path.into_inner().id;
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        RatingModel,
+        "SELECT * FROM ratings WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+        rating_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let rating = match query_result {
+        Ok(rating) => rating,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    if let Some(rating) = &rating {
+        if !etag::if_match(&if_match, &etag::entity_tag(rating.updated_at)) {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("Rating {} has been modified since it was fetched", rating_id)
+            });
+            return HttpResponse::PreconditionFailed().json(json_error);
+        }
+    }
+
+    let now = Utc::now();
+
+    let query_result = sqlx::query!(
+        "UPDATE ratings SET deleted_at = $1, updated_at = $1 WHERE id = $2 AND deleted_at IS NULL",
+        now,
+        rating_id
+    )
+        .execute(&mut tx)
+        .await;
+
+    let rows_affected = match query_result {
+        Ok(result) => result.rows_affected(),
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    if rows_affected == 0 {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("No results found with ID: {}", rating_id)
+        });
+        return HttpResponse::InternalServerError().json(json_error)
+    };
+
+    let query_result = audit::record(&mut tx, &audit, "delete", "ratings", rating_id, rating.as_ref().and_then(audit::snapshot), None).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "message": "successfully deleted"
+    });
+
+    HttpResponse::Ok().json(json_response)
+}
+
+#[get("/trash")]
+async fn get_deleted_ratings(data: web::Data<AppState>, _admin: Admin, opts: web::Query<FilterOptions>) -> impl Responder {
+    let limit = opts.limit.unwrap_or(10);
+    let offset = opts.offset.unwrap_or(1).saturating_sub(1) * limit;
+
+    let query_result = sqlx::query_as!(
+        RatingModel,
+        "SELECT * FROM ratings WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC LIMIT $1 OFFSET $2",
+        limit as i32,
+        offset as i32
+    )
+        .fetch_all(&data.db)
+        .await;
+
+    let ratings = match query_result {
+        Ok(ratings) => ratings,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_count = sqlx::query_scalar("SELECT COUNT(*) FROM ratings WHERE deleted_at IS NOT NULL")
+        .fetch_one(&data.db)
+        .await;
+
+    let deleted_count: i64 = match query_count {
+        Ok(count) => count,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+    let json_response = json!({
+        "status": "success",
+        "count": deleted_count,
+        "data": ratings
+    });
+
+    HttpResponse::Ok().json(json_response)
+}
+
+#[post("/{id}/restore")]
+async fn restore_rating(data: web::Data<AppState>, _admin: Admin, audit: AuditContext, path: web::Path<PathOptions>) -> impl Responder {
+    let rating_id = path.into_inner().id;
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        RatingModel,
+        "SELECT * FROM ratings WHERE id = $1 AND deleted_at IS NOT NULL FOR UPDATE",
+        rating_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let before = match query_result {
+        Ok(Some(rating)) => audit::snapshot(&rating),
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no deleted rating with ID: {}", rating_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let now = Utc::now();
+
+    let query_result = sqlx::query_as!(
+        RatingModel,
+        "UPDATE ratings SET deleted_at = NULL, updated_at = $1 WHERE id = $2 RETURNING *",
+        now,
+        rating_id
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let rating = match query_result {
+        Ok(rating) => rating,
+        Err(sqlx::Error::Database(err)) if err.code().as_deref() == Some("23505") => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("Cannot restore rating {}: {}", rating_id, err)
+            });
+            return HttpResponse::Conflict().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = audit::record(&mut tx, &audit, "restore", "ratings", rating_id, before, audit::snapshot(&rating)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": rating
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(rating.updated_at))).json(json_response)
+}
+
+#[get("/moderation-queue")]
+async fn get_moderation_queue(data: web::Data<AppState>, _admin: Admin, opts: web::Query<FilterOptions>) -> impl Responder {
+    let limit = opts.limit.unwrap_or(10);
+    let offset = opts.offset.unwrap_or(1).saturating_sub(1) * limit;
+
+    let query_result = sqlx::query_as!(
+        RatingModel,
+        "SELECT * FROM ratings WHERE moderation_status = 'pending' AND deleted_at IS NULL ORDER BY created_at LIMIT $1 OFFSET $2",
+        limit as i32,
+        offset as i32
+    )
+        .fetch_all(&data.db)
+        .await;
+
+    let ratings = match query_result {
+        Ok(ratings) => ratings,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_count = sqlx::query_scalar("SELECT COUNT(*) FROM ratings WHERE moderation_status = 'pending' AND deleted_at IS NULL")
+        .fetch_one(&data.db)
+        .await;
+
+    let pending_count: i64 = match query_count {
+        Ok(count) => count,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+    let json_response = json!({
+        "status": "success",
+        "count": pending_count,
+        "data": ratings
+    });
+
+    HttpResponse::Ok().json(json_response)
+}
+
+/// Flagged ratings grouped by product and hour, largest clusters first.
+#[get("/abuse-report")]
+async fn get_abuse_report(data: web::Data<AppState>, _admin: Admin, opts: web::Query<FilterOptions>) -> impl Responder {
+    let limit = opts.limit.unwrap_or(10);
+    let offset = opts.offset.unwrap_or(1).saturating_sub(1) * limit;
+
+    let query_result = sqlx::query_as!(
+        AbuseClusterModel,
+        r#"SELECT
+                r.product_id,
+                date_trunc('hour', r.created_at) AS "window_start!",
+                COUNT(DISTINCT r.id) AS "rating_count!",
+                array_agg(DISTINCT flag) AS "flags!",
+                array_agg(DISTINCT r.id) AS "rating_ids!",
+                array_agg(DISTINCT r.user_id) AS "user_ids!"
+            FROM ratings r CROSS JOIN LATERAL unnest(r.abuse_flags) AS flag
+            WHERE r.deleted_at IS NULL
+            GROUP BY r.product_id, date_trunc('hour', r.created_at)
+            ORDER BY 3 DESC, 2 DESC
+            LIMIT $1 OFFSET $2"#,
+        limit as i32,
+        offset as i32
+    )
+        .fetch_all(&data.db)
+        .await;
+
+    let clusters = match query_result {
+        Ok(clusters) => clusters,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_count = sqlx::query_scalar("SELECT COUNT(DISTINCT (product_id, date_trunc('hour', created_at))) FROM ratings WHERE deleted_at IS NULL AND cardinality(abuse_flags) > 0")
+        .fetch_one(&data.db)
+        .await;
+
+    let cluster_count: i64 = match query_count {
+        Ok(count) => count,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+    let json_response = json!({
+        "status": "success",
+        "count": cluster_count,
+        "data": clusters
+    });
+
+    HttpResponse::Ok().json(json_response)
+}
+
+#[post("/{id}/moderation")]
+async fn moderate_rating(data: web::Data<AppState>, _admin: Admin, audit: AuditContext, path: web::Path<PathOptions>, body: web::Json<ModerateRating>) -> impl Responder {
+    let rating_id = path.into_inner().id;
+    let reason = body.reason.as_deref().map(str::trim).filter(|reason| !reason.is_empty());
+
+    if body.status == ModerationStatus::Pending {
+        let json_error = json!({
+            "status": "error",
+            "message": "status must be approved or rejected"
+        });
+        return HttpResponse::BadRequest().json(json_error);
+    }
+    if body.status == ModerationStatus::Rejected && reason.is_none() {
+        let json_error = json!({
+            "status": "error",
+            "message": "A reason is required to reject a rating"
+        });
+        return HttpResponse::BadRequest().json(json_error);
+    }
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        RatingModel,
+        "SELECT * FROM ratings WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+        rating_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let before = match query_result {
+        Ok(Some(rating)) => audit::snapshot(&rating),
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no rating with ID: {}", rating_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let now = Utc::now();
+
+    let query_result = sqlx::query_as!(
+        RatingModel,
+        "UPDATE ratings SET moderation_status = $1, moderation_reason = $2, moderated_at = $3, updated_at = $3 WHERE id = $4 RETURNING *",
+        body.status.as_str(),
+        reason,
+        now,
+        rating_id
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let rating = match query_result {
+        Ok(rating) => rating,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = audit::record(&mut tx, &audit, "update", "ratings", rating_id, before, audit::snapshot(&rating)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": rating
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(rating.updated_at))).json(json_response)
+}
+
+/// Prior for the Bayesian score while nothing in the catalogue is rated yet.
+const DEFAULT_PRIOR_MEAN: f64 = 3.0;
+
+/// Rating statistics for one product, from the `product_rating_stats` view.
+/// Registered under the `/products` scope.
+#[get("/{id}/rating-summary")]
+pub async fn get_product_rating_summary(data: web::Data<AppState>, path: web::Path<PathOptions>) -> impl Responder {
+    let product_id = path.into_inner().id;
+
+    let query_result = sqlx::query!(
+        r#"SELECT
+                COALESCE(s.count_1, 0) AS "count_1!", COALESCE(s.count_2, 0) AS "count_2!", COALESCE(s.count_3, 0) AS "count_3!",
+                COALESCE(s.count_4, 0) AS "count_4!", COALESCE(s.count_5, 0) AS "count_5!",
+                (SELECT SUM(rating_sum)::DOUBLE PRECISION / NULLIF(SUM(rating_count), 0) FROM product_rating_stats) AS prior_mean
+            FROM products p LEFT JOIN product_rating_stats s ON s.product_id = p.id
+            WHERE p.id = $1 AND p.deleted_at IS NULL"#,
+        product_id
+    )
+        .fetch_optional(&data.db)
+        .await;
+
+    let stats = match query_result {
+        Ok(Some(stats)) => stats,
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no product with ID: {}", product_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let summary = RatingSummary::new(
+        [stats.count_1, stats.count_2, stats.count_3, stats.count_4, stats.count_5],
+        stats.prior_mean.unwrap_or(DEFAULT_PRIOR_MEAN),
+        data.config.rating_prior_weight
+    );
+    let json_response = json!({
+        "status": "success",
+        "data": summary
+    });
+    HttpResponse::Ok().json(json_response)
+}
+
+/// Rating statistics across the live products of a category. Registered
+/// under the `/categories` scope.
+#[get("/{id}/rating-summary")]
+pub async fn get_category_rating_summary(data: web::Data<AppState>, path: web::Path<PathOptions>) -> impl Responder {
+    let category_id = path.into_inner().id;
+
+    let query_result = sqlx::query!(
+        r#"SELECT
+                COALESCE(SUM(s.count_1), 0)::BIGINT AS "count_1!", COALESCE(SUM(s.count_2), 0)::BIGINT AS "count_2!",
+                COALESCE(SUM(s.count_3), 0)::BIGINT AS "count_3!", COALESCE(SUM(s.count_4), 0)::BIGINT AS "count_4!",
+                COALESCE(SUM(s.count_5), 0)::BIGINT AS "count_5!",
+                (SELECT SUM(rating_sum)::DOUBLE PRECISION / NULLIF(SUM(rating_count), 0) FROM product_rating_stats) AS prior_mean
+            FROM categories c
+                LEFT JOIN products p ON p.category_id = c.id AND p.deleted_at IS NULL
+                LEFT JOIN product_rating_stats s ON s.product_id = p.id
+            WHERE c.id = $1 AND c.deleted_at IS NULL
+            GROUP BY c.id"#,
+        category_id
+    )
+        .fetch_optional(&data.db)
+        .await;
+
+    let stats = match query_result {
+        Ok(Some(stats)) => stats,
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no category with ID: {}", category_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let summary = RatingSummary::new(
+        [stats.count_1, stats.count_2, stats.count_3, stats.count_4, stats.count_5],
+        stats.prior_mean.unwrap_or(DEFAULT_PRIOR_MEAN),
+        data.config.rating_prior_weight
+    );
+    let json_response = json!({
+        "status": "success",
+        "data": summary
+    });
+    HttpResponse::Ok().json(json_response)
+}
+
+/// Records the caller's up or down vote on a review, replacing any earlier
+/// vote, and keeps the denormalized counts on the rating in step.
+#[post("/{id}/votes")]
+async fn vote_rating(data: web::Data<AppState>, audit: AuditContext, user: CurrentUser, path: web::Path<PathOptions>, body: web::Json<CastVote>) -> impl Responder {
+    let rating_id = path.into_inner().id;
+    let CurrentUser(user_id) = user;
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        RatingModel,
+        "SELECT * FROM ratings WHERE id = $1 AND deleted_at IS NULL AND moderation_status = 'approved' FOR UPDATE",
+        rating_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let rating = match query_result {
+        Ok(Some(rating)) => rating,
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no rating with ID: {}", rating_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    if rating.user_id == user_id {
+        let json_error = json!({
+            "status": "error",
+            "message": "Users cannot vote on their own rating"
+        });
+        return HttpResponse::Forbidden().json(json_error);
+    }
+
+    let query_result = sqlx::query_scalar!(
+        "SELECT id FROM users WHERE id = $1 AND deleted_at IS NULL FOR SHARE",
+        user_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    match query_result {
+        Ok(Some(_)) => {},
+        Ok(None) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("There is no user with ID: {}", user_id)
+            });
+            return HttpResponse::UnprocessableEntity().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    let query_result = sqlx::query_as!(
+        RatingVoteModel,
+        "SELECT * FROM rating_votes WHERE rating_id = $1 AND user_id = $2",
+        rating_id,
+        user_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let existing = match query_result {
+        Ok(existing) => existing,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+    let previous = existing.as_ref().and_then(|vote| Vote::parse(&vote.vote));
+
+    if let (Some(vote), Some(previous)) = (&existing, previous) {
+        if previous == body.vote {
+            let json_response = json!({
+                "status": "success",
+                "data": vote,
+                "rating": rating
+            });
+            return HttpResponse::Ok().json(json_response);
+        }
+    }
+    let before = existing.as_ref().and_then(audit::snapshot);
+
+    let query_result = sqlx::query_as!(
+        RatingVoteModel,
+        "INSERT INTO rating_votes (rating_id, user_id, vote) VALUES ($1, $2, $3)
+            ON CONFLICT (rating_id, user_id) DO UPDATE SET vote = EXCLUDED.vote, updated_at = NOW()
+            RETURNING *",
+        rating_id,
+        user_id,
+        body.vote.as_str()
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let vote = match query_result {
+        Ok(vote) => vote,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let (added_helpful, added_unhelpful) = body.vote.counts();
+    let (removed_helpful, removed_unhelpful) = previous.map(|previous| previous.counts()).unwrap_or((0, 0));
+
+    // Votes leave updated_at alone so they never invalidate the author's ETag.
+    let query_result = sqlx::query_as!(
+        RatingModel,
+        "UPDATE ratings SET helpful_count = helpful_count + $1, unhelpful_count = unhelpful_count + $2 WHERE id = $3 RETURNING *",
+        added_helpful - removed_helpful,
+        added_unhelpful - removed_unhelpful,
+        rating_id
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let rating = match query_result {
+        Ok(rating) => rating,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let action = if before.is_some() { "update" } else { "create" };
+    let query_result = audit::record(&mut tx, &audit, action, "rating_votes", vote.id, before, audit::snapshot(&vote)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": vote,
+        "rating": rating
+    });
+    HttpResponse::Ok().json(json_response)
+}
+
+/// Creates or replaces the caller's rating of a product. Repeating the same
+/// request leaves the rating, and its ETag, untouched. Registered under the
+/// `/products` scope.
+#[put("/{id}/ratings/me")]
+pub async fn put_my_rating(data: web::Data<AppState>, audit: AuditContext, user: CurrentUser, path: web::Path<PathOptions>, body: web::Json<PutRating>) -> impl Responder {
+    let product_id = path.into_inner().id;
+    let CurrentUser(user_id) = user;
+
+    if !(1..=5).contains(&body.rating) {
+        let json_error = json!({
+            "status": "error",
+            "message": "rating must be between 1 and 5"
+        });
+        return HttpResponse::BadRequest().json(json_error);
+    }
+
+    if let Err(message) = moderation::validate_title(body.title.as_deref()) {
+        let json_error = json!({
+            "status": "error",
+            "message": message
+        });
+        return HttpResponse::BadRequest().json(json_error);
+    }
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_scalar!(
+        "SELECT id FROM products WHERE id = $1 AND deleted_at IS NULL FOR SHARE",
+        product_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    match query_result {
+        Ok(Some(_)) => {},
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no product with ID: {}", product_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    let query_result = sqlx::query_scalar!(
+        "SELECT id FROM users WHERE id = $1 AND deleted_at IS NULL FOR SHARE",
+        user_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    match query_result {
+        Ok(Some(_)) => {},
+        Ok(None) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("There is no user with ID: {}", user_id)
+            });
+            return HttpResponse::UnprocessableEntity().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    let verified_purchase = match moderation::has_purchased(&mut tx, user_id, product_id).await {
+        Ok(verified_purchase) => verified_purchase,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+    if !verified_purchase && data.config.rating_purchase_policy == PurchasePolicy::Reject {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("Only customers who bought product {} can rate it", product_id)
+        });
+        return HttpResponse::Forbidden().json(json_error);
+    }
+
+    let query_result = sqlx::query_as!(
+        RatingModel,
+        "SELECT * FROM ratings WHERE user_id = $1 AND product_id = $2 AND deleted_at IS NULL FOR UPDATE",
+        user_id,
+        product_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let existing = match query_result {
+        Ok(existing) => existing,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let unchanged = |rating: &&RatingModel| {
+        rating.rating == body.rating
+            && rating.verified_purchase == verified_purchase
+            && rating.title == body.title
+            && rating.body == body.body
+    };
+    if let Some(rating) = existing.as_ref().filter(unchanged) {
+        let json_response = json!({
+            "status": "success",
+            "data": rating
+        });
+        return HttpResponse::Ok().insert_header(ETag(etag::entity_tag(rating.updated_at))).json(json_response);
+    }
+    let before = existing.as_ref().and_then(audit::snapshot);
+
+    // Abuse rules only judge new ratings.
+    let abuse_flags = match existing.as_ref() {
+        Some(rating) => rating.abuse_flags.clone(),
+        None => match moderation::abuse_flags(&mut tx, &data.config.abuse_rules, user_id, product_id).await {
+            Ok(abuse_flags) => abuse_flags,
+            Err(err) => {
+                let json_error = json!({
+                    "status": "error",
+                    "message": format!("{}", err)
+                });
+                return HttpResponse::InternalServerError().json(json_error);
+            }
+        }
+    };
+
+    let (moderation_status, moderation_reason) = match existing.as_ref() {
+        Some(rating) if rating.title == body.title && rating.body == body.body => {
+            (rating.moderation_status.clone(), rating.moderation_reason.clone())
+        },
+        existing => {
+            let current = existing
+                .and_then(|rating| ModerationStatus::parse(&rating.moderation_status))
+                .unwrap_or(ModerationStatus::Approved);
+            let (status, reason) = moderation::review_status(
+                &data.config.banned_words,
+                current,
+                existing.and_then(|rating| rating.moderation_reason.clone()),
+                body.title.as_deref(),
+                body.body.as_deref()
+            );
+            let (status, reason) = match existing {
+                Some(_) => (status, reason),
+                None => moderation::quarantine(&data.config.abuse_rules, &abuse_flags, status, reason)
+            };
+            (status.as_str().to_string(), reason)
+        }
+    };
+
+    // The conflict clause covers a concurrent first PUT from the same user.
+    let query_result = sqlx::query_as!(
+        RatingModel,
+        "INSERT INTO ratings (rating, product_id, user_id, verified_purchase, title, body, moderation_status, moderation_reason, abuse_flags) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            ON CONFLICT (user_id, product_id) WHERE deleted_at IS NULL
+            DO UPDATE SET rating = EXCLUDED.rating, verified_purchase = EXCLUDED.verified_purchase, title = EXCLUDED.title, body = EXCLUDED.body,
+                moderation_status = EXCLUDED.moderation_status, moderation_reason = EXCLUDED.moderation_reason, updated_at = NOW()
+            RETURNING *",
+        body.rating,
+        product_id,
+        user_id,
+        verified_purchase,
+        body.title,
+        body.body,
+        moderation_status,
+        moderation_reason,
+        &abuse_flags
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let rating = match query_result {
+        Ok(rating) => rating,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let action = if before.is_some() { "update" } else { "create" };
+    let query_result = audit::record(&mut tx, &audit, action, "ratings", rating.id, before, audit::snapshot(&rating)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": rating
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(rating.updated_at))).json(json_response)
 }
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     let scope = web::scope("/ratings")
         .service(get_ratings)
+        .service(get_deleted_ratings)
+        .service(get_moderation_queue)
+        .service(get_abuse_report)
         .service(create_rating)
         .service(get_rating)
+        .service(json_patch_rating)
         .service(update_rating)
-        .service(delete_rating);
+        .service(delete_rating)
+        .service(restore_rating)
+        .service(moderate_rating)
+        .service(vote_rating);
     cfg.service(scope);
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, App};
+    use serde_json::Value;
+    use sqlx::postgres::PgPoolOptions;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::config::Config;
+
+    /// `helpful_score` is a generated column, so the ranking can only be
+    /// checked against the database. Needs `DATABASE_URL`, like the build.
+    #[actix_web::test]
+    async fn helpful_sort_ranks_by_the_wilson_lower_bound() {
+        dotenv::dotenv().ok();
+        let settings = Config::init();
+        let db = PgPoolOptions::new().max_connections(2).connect(&settings.database_url).await.unwrap();
+
+        let tag = Uuid::new_v4().simple().to_string();
+        let category_id = sqlx::query_scalar!("INSERT INTO categories (category_name) VALUES ($1) RETURNING id", format!("helpful-sort-{}", tag))
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        let product_id = sqlx::query_scalar!(
+            "INSERT INTO products (product_name, price, category_id) VALUES ($1, 1, $2) RETURNING id",
+            format!("helpful-sort-{}", tag),
+            category_id
+        )
+            .fetch_one(&db)
+            .await
+            .unwrap();
+
+        // (helpful, unhelpful) in the order the sort must return them: 40 of 50
+        // beats 2 of 2, which beats a single down vote and no votes at all.
+        let votes = [(40, 10), (2, 0), (0, 1), (0, 0)];
+        let mut user_ids = Vec::new();
+        let mut expected = Vec::new();
+        for (index, (helpful, unhelpful)) in votes.into_iter().enumerate().rev() {
+            let user_id = sqlx::query_scalar!("INSERT INTO users (username) VALUES ($1) RETURNING id", format!("helpful-sort-{}-{}", tag, index))
+                .fetch_one(&db)
+                .await
+                .unwrap();
+            let rating_id = sqlx::query_scalar!(
+                "INSERT INTO ratings (rating, product_id, user_id, helpful_count, unhelpful_count) VALUES (5, $1, $2, $3, $4) RETURNING id",
+                product_id,
+                user_id,
+                helpful,
+                unhelpful
+            )
+                .fetch_one(&db)
+                .await
+                .unwrap();
+            user_ids.push(user_id);
+            expected.insert(0, rating_id.to_string());
+        }
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AppState { db: db.clone(), config: settings }))
+                .configure(super::config)
+        ).await;
+        let request = test::TestRequest::get().uri(&format!("/ratings?product_id={}&sort=helpful", product_id)).to_request();
+        let body: Value = test::call_and_read_body_json(&app, request).await;
+
+        sqlx::query!("DELETE FROM ratings WHERE product_id = $1", product_id).execute(&db).await.unwrap();
+        sqlx::query!("DELETE FROM users WHERE id = ANY($1)", &user_ids).execute(&db).await.unwrap();
+        sqlx::query!("DELETE FROM products WHERE id = $1", product_id).execute(&db).await.unwrap();
+        sqlx::query!("DELETE FROM categories WHERE id = $1", category_id).execute(&db).await.unwrap();
+
+        let ranked: Vec<&str> = body["data"].as_array().unwrap().iter().map(|rating| rating["id"].as_str().unwrap()).collect();
+        assert_eq!(ranked, expected);
+    }
+}
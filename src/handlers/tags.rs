@@ -0,0 +1,459 @@
+use actix_web::{get, post, put, delete, web, Responder, HttpResponse, http::header::ETag};
+use serde_json::json;
+
+use crate::{AppState, audit::{self, AuditContext}, auth::Admin, etag, schema::PathOptions, models::tags::{self, TagModel, CreateTag, ProductTagPath}};
+
+const MAX_TAG_NAME_LENGTH: usize = 50;
+
+#[get("")]
+async fn get_tags(data: web::Data<AppState>) -> impl Responder {
+    let query_result = sqlx::query_as!(
+        TagModel,
+        "SELECT * FROM tags ORDER BY tag_name"
+    )
+        .fetch_all(&data.db)
+        .await;
+
+    let tags = match query_result {
+        Ok(tags) => tags,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let json_response = json!({
+        "status": "success",
+        "count": tags.len(),
+        "data": tags
+    });
+    HttpResponse::Ok().json(json_response)
+}
+
+#[post("")]
+async fn create_tag(data: web::Data<AppState>, _admin: Admin, audit: AuditContext, body: web::Json<CreateTag>) -> impl Responder {
+    let tag_name = tags::normalize(&body.tag_name);
+    if tag_name.is_empty() || tag_name.contains(',') || tag_name.chars().count() > MAX_TAG_NAME_LENGTH {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("tag_name must be 1 to {} characters without commas", MAX_TAG_NAME_LENGTH)
+        });
+        return HttpResponse::BadRequest().json(json_error);
+    }
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        TagModel,
+        "INSERT INTO tags (tag_name) VALUES ($1) RETURNING *",
+        tag_name
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let tag = match query_result {
+        Ok(tag) => tag,
+        Err(sqlx::Error::Database(err)) if err.code().as_deref() == Some("23505") => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("A tag named {} already exists", tag_name)
+            });
+            return HttpResponse::Conflict().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = audit::record(&mut tx, &audit, "create", "tags", tag.id, None, audit::snapshot(&tag)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": tag
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(tag.updated_at))).json(json_response)
+}
+
+#[get("/{id}")]
+async fn get_tag(data: web::Data<AppState>, path: web::Path<PathOptions>) -> impl Responder {
+    let tag_id = path.into_inner().id;
+
+    let query_result = sqlx::query_as!(
+        TagModel,
+        "SELECT * FROM tags WHERE id = $1",
+        tag_id
+    )
+        .fetch_optional(&data.db)
+        .await;
+
+    let tag = match query_result {
+        Ok(Some(tag)) => tag,
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no tag with ID: {}", tag_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let etag = etag::entity_tag(tag.updated_at);
+    let json_response = json!({
+        "status": "success",
+        "data": tag
+    });
+    HttpResponse::Ok().insert_header(ETag(etag)).json(json_response)
+}
+
+/// Removes the tag from every product it was attached to.
+#[delete("/{id}")]
+async fn delete_tag(data: web::Data<AppState>, _admin: Admin, audit: AuditContext, path: web::Path<PathOptions>) -> impl Responder {
+    let tag_id = path.into_inner().id;
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        TagModel,
+        "DELETE FROM tags WHERE id = $1 RETURNING *",
+        tag_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let tag = match query_result {
+        Ok(Some(tag)) => tag,
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no tag with ID: {}", tag_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = audit::record(&mut tx, &audit, "delete", "tags", tag_id, audit::snapshot(&tag), None).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "message": format!("Tag removed with ID: {}", tag_id)
+    });
+    HttpResponse::Ok().json(json_response)
+}
+
+/// Registered under the `/products` scope.
+#[get("/{id}/tags")]
+pub async fn get_product_tags(data: web::Data<AppState>, path: web::Path<PathOptions>) -> impl Responder {
+    let product_id = path.into_inner().id;
+
+    let query_result = sqlx::query_scalar!(
+        "SELECT id FROM products WHERE id = $1 AND deleted_at IS NULL",
+        product_id
+    )
+        .fetch_optional(&data.db)
+        .await;
+
+    match query_result {
+        Ok(Some(_)) => {},
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no product with ID: {}", product_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    let query_result = sqlx::query_as!(
+        TagModel,
+        "SELECT t.* FROM tags t JOIN product_tags pt ON pt.tag_id = t.id WHERE pt.product_id = $1 ORDER BY t.tag_name",
+        product_id
+    )
+        .fetch_all(&data.db)
+        .await;
+
+    let tags = match query_result {
+        Ok(tags) => tags,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let json_response = json!({
+        "status": "success",
+        "count": tags.len(),
+        "data": tags
+    });
+    HttpResponse::Ok().json(json_response)
+}
+
+/// Attaches a tag to a product; attaching it again has no effect. Registered
+/// under the `/products` scope.
+#[put("/{id}/tags/{tag_id}")]
+pub async fn attach_tag(data: web::Data<AppState>, _admin: Admin, audit: AuditContext, path: web::Path<ProductTagPath>) -> impl Responder {
+    let ProductTagPath { id: product_id, tag_id } = path.into_inner();
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_scalar!(
+        "SELECT id FROM products WHERE id = $1 AND deleted_at IS NULL FOR SHARE",
+        product_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    match query_result {
+        Ok(Some(_)) => {},
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no product with ID: {}", product_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    let query_result = sqlx::query_as!(
+        TagModel,
+        "SELECT * FROM tags WHERE id = $1 FOR SHARE",
+        tag_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let tag = match query_result {
+        Ok(Some(tag)) => tag,
+        Ok(None) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("There is no tag with ID: {}", tag_id)
+            });
+            return HttpResponse::UnprocessableEntity().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query!(
+        "INSERT INTO product_tags (product_id, tag_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        product_id,
+        tag_id
+    )
+        .execute(&mut tx)
+        .await;
+
+    let attached = match query_result {
+        Ok(result) => result.rows_affected() > 0,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    if attached {
+        let after = json!({ "product_id": product_id, "tag_id": tag_id, "tag_name": tag.tag_name });
+        let query_result = audit::record(&mut tx, &audit, "create", "product_tags", product_id, None, Some(after)).await;
+        if let Err(err) = query_result {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": tag
+    });
+    HttpResponse::Ok().json(json_response)
+}
+
+/// Registered under the `/products` scope.
+#[delete("/{id}/tags/{tag_id}")]
+pub async fn detach_tag(data: web::Data<AppState>, _admin: Admin, audit: AuditContext, path: web::Path<ProductTagPath>) -> impl Responder {
+    let ProductTagPath { id: product_id, tag_id } = path.into_inner();
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query!(
+        "DELETE FROM product_tags WHERE product_id = $1 AND tag_id = $2",
+        product_id,
+        tag_id
+    )
+        .execute(&mut tx)
+        .await;
+
+    let rows_affected = match query_result {
+        Ok(result) => result.rows_affected(),
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    if rows_affected == 0 {
+        let json_error = json!({
+            "status": "not found",
+            "message": format!("Tag {} is not attached to product {}", tag_id, product_id)
+        });
+        return HttpResponse::NotFound().json(json_error);
+    }
+
+    let before = json!({ "product_id": product_id, "tag_id": tag_id });
+    let query_result = audit::record(&mut tx, &audit, "delete", "product_tags", product_id, Some(before), None).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "message": format!("Tag {} detached from product {}", tag_id, product_id)
+    });
+    HttpResponse::Ok().json(json_response)
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    let scope = web::scope("/tags")
+        .service(get_tags)
+        .service(create_tag)
+        .service(get_tag)
+        .service(delete_tag);
+
+    cfg.service(scope);
+}
@@ -1,75 +1,154 @@
-use actix_web::{get, post, patch, delete, web, Responder, HttpResponse};
+use actix_web::{get, post, patch, delete, web, Responder, HttpResponse, http::header::{ETag, IfMatch}};
 use serde_json::json;
+use json_patch::Patch as JsonPatch;
 use chrono::Utc;
 
-use crate::{AppState, schema::{FilterOptions, PathOptions}, models::users::{UserModel, CreateUser, UpdateUser}};
+use crate::{AppState, audit::{self, AuditContext}, auth::{Admin, CurrentUser}, etag, inventory, patching, schema::{DeleteOptions, DeleteStrategy, DeletedOptions, FilterOptions, PathOptions}, handlers::orders, models::orders::OrderModel, models::purchases::PurchaseModel, models::users::{UserModel, CreateUser, UpdateUser, ConfirmEmail, ClaimPurchase}};
+
+/// Verification only changes through `POST /users/{id}/email-verification/confirm`.
+const IMMUTABLE_FIELDS: [&str; 5] = ["id", "created_at", "updated_at", "deleted_at", "email_verified_at"];
 
 #[get("")]
-async fn get_users(data: web::Data<AppState>, opts: web::Query<FilterOptions>) -> impl Responder {
+async fn get_users(data: web::Data<AppState>, admin: Option<Admin>, opts: web::Query<FilterOptions>) -> impl Responder {
     let limit = opts.limit.unwrap_or(10);
-    let offset = (opts.offset.unwrap_or(1) - 1) * 1;
+    let offset = opts.offset.unwrap_or(1).saturating_sub(1) * limit;
+
+    let include_deleted = opts.include_deleted.unwrap_or(false);
+    if include_deleted && admin.is_none() {
+        let json_error = json!({
+            "status": "error",
+            "message": "include_deleted requires admin credentials"
+        });
+        return HttpResponse::Forbidden().json(json_error);
+    }
 
     let query_result = sqlx::query_as!(
         UserModel,
-        "SELECT * FROM users ORDER BY created_at DESC LIMIT $1 OFFSET $2",
+        "SELECT * FROM users WHERE (deleted_at IS NULL OR $1) ORDER BY created_at DESC LIMIT $2 OFFSET $3",
+        include_deleted,
         limit as i32,
         offset as i32
     )
         .fetch_all(&data.db)
         .await;
 
-    match query_result {
-        Ok(users) => {
-            let query_count = sqlx::query_scalar("SELECT COUNT(*) FROM users").fetch_one(&data.db).await;
-
-            let users_count: i64 = match query_count {
-                Ok(count) => count,
-                Err(err) => {
-                   let json_error = json!({
-                        "status": "error",
-                        "message": format!("{}", err)
-                    }); 
-                    return HttpResponse::InternalServerError().json(json_error)
-                }
-            };
-            let json_response = json!({
-                "status": "success",
-                "count": users_count,
-                "data": users
-            });
-
-            return HttpResponse::Ok().json(json_response)
-        },
+    let users = match query_result {
+        Ok(users) => users,
         Err(err) => {
             let json_error = json!({
                 "status": "error",
                 "message": format!("{}", err)
             });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_count = sqlx::query_scalar("SELECT COUNT(*) FROM users WHERE (deleted_at IS NULL OR $1)")
+        .bind(include_deleted)
+        .fetch_one(&data.db)
+        .await;
+
+    let users_count: i64 = match query_count {
+        Ok(count) => count,
+        Err(err) => {
+           let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            }); 
             return HttpResponse::InternalServerError().json(json_error)
         }
-    }
+    };
+    let json_response = json!({
+        "status": "success",
+        "count": users_count,
+        "data": users
+    });
+
+    HttpResponse::Ok().json(json_response)
 }
 
 #[post("")]
-async fn create_user(data: web::Data<AppState>, body: web::Json<CreateUser>) -> impl Responder {
+async fn create_user(data: web::Data<AppState>, audit: AuditContext, body: web::Json<CreateUser>) -> impl Responder {
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
     let query_result = sqlx::query_as!(
         UserModel,
         "INSERT INTO users (username, email) VALUES ($1, $2) RETURNING *",
         body.username.to_string(),
-        body.email.to_owned().unwrap_or("".to_string())
+        body.email.to_owned().filter(|email| !email.trim().is_empty())
     )
-        .fetch_one(&data.db)
+        .fetch_one(&mut tx)
         .await;
 
-    match query_result {
-        Ok(user) => {
-            let json_response = json!({
-                "status": "success",
-                "data": user
+    let user = match query_result {
+        Ok(user) => user,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
             });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+    let user_id = user.id;
 
-            return HttpResponse::Ok().json(json_response);
-        },
+    let query_result = audit::record(&mut tx, &audit, "create", "users", user_id, None, audit::snapshot(&user)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": user
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(user.updated_at))).json(json_response)
+}
+
+#[get("/{id}")]
+async fn get_user(data: web::Data<AppState>, admin: Option<Admin>, path: web::Path<PathOptions>, opts: web::Query<DeletedOptions>) -> impl Responder {
+    let user_id = path.into_inner().id;
+
+    let include_deleted = opts.include_deleted.unwrap_or(false);
+    if include_deleted && admin.is_none() {
+        let json_error = json!({
+            "status": "error",
+            "message": "include_deleted requires admin credentials"
+        });
+        return HttpResponse::Forbidden().json(json_error);
+    }
+
+    let query_result = sqlx::query_as!(
+        UserModel,
+        "SELECT * FROM users WHERE id = $1 AND (deleted_at IS NULL OR $2)",
+        user_id,
+        include_deleted
+    )
+        .fetch_one(&data.db)
+        .await;
+
+    let user = match query_result {
+        Ok(user) => user,
         Err(err) => {
             let json_error = json!({
                 "status": "error",
@@ -78,51 +157,142 @@ async fn create_user(data: web::Data<AppState>, body: web::Json<CreateUser>) ->
 
             return HttpResponse::InternalServerError().json(json_error);
         }
-    }
+    };
+
+    let etag = etag::entity_tag(user.updated_at);
+    let json_response = json!({
+        "status": "succcess",
+        "data": user
+    });
+    HttpResponse::Ok().insert_header(ETag(etag)).json(json_response)
 }
 
-#[get("/{id}")]
-async fn get_user(data: web::Data<AppState>, path: web::Path<PathOptions>) -> impl Responder {
+
+#[patch("/{id}", guard = "patching::is_json_patch")]
+async fn json_patch_user(data: web::Data<AppState>, audit: AuditContext, path: web::Path<PathOptions>, if_match: Option<web::Header<IfMatch>>, body: web::Json<JsonPatch>) -> impl Responder {
     let user_id = path.into_inner().id;
 
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
     let query_result = sqlx::query_as!(
         UserModel,
-        "SELECT * FROM users WHERE id = $1",
+        "SELECT * FROM users WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
         user_id
     )
-        .fetch_one(&data.db)
+        .fetch_one(&mut tx)
         .await;
 
-    match query_result {
-        Ok(user) => {
-            let json_response = json!({
-                "status": "succcess",
-                "data": user
+    let user = match query_result {
+        Ok(user) => user,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
             });
-            return HttpResponse::Ok().json(json_response);
-        },
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+    let before = audit::snapshot(&user);
+
+    if !etag::if_match(&if_match, &etag::entity_tag(user.updated_at)) {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("User {} has been modified since it was fetched", user_id)
+        });
+        return HttpResponse::PreconditionFailed().json(json_error);
+    }
+
+    let user: UserModel = match patching::apply(&user, &body, &IMMUTABLE_FIELDS) {
+        Ok(user) => user,
         Err(err) => {
             let json_error = json!({
                 "status": "error",
                 "message": format!("{}", err)
             });
+            return HttpResponse::build(err.status()).json(json_error);
+        }
+    };
+
+    let email = user.email.filter(|email| !email.trim().is_empty());
+
+    let now = Utc::now();
+
+    let query_result = sqlx::query_as!(
+        UserModel,
+        "UPDATE users SET username = $1, email = $2, email_verified_at = CASE WHEN email IS DISTINCT FROM $2::varchar THEN NULL ELSE email_verified_at END, updated_at = $3 WHERE id = $4 RETURNING *",
+        user.username,
+        email,
+        now,
+        user_id
+    )
+        .fetch_one(&mut tx)
+        .await;
 
+    let user = match query_result {
+        Ok(user) => user,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
             return HttpResponse::InternalServerError().json(json_error);
         }
+    };
+
+    let query_result = audit::record(&mut tx, &audit, "update", "users", user_id, before, audit::snapshot(&user)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
     }
-}
 
+    let json_response = json!({
+        "status": "success",
+        "data": user
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(user.updated_at))).json(json_response)
+}
 
 #[patch("/{id}")]
-async fn update_user(data: web::Data<AppState>, path: web::Path<PathOptions>, body: web::Json<UpdateUser>) -> impl Responder {
+async fn update_user(data: web::Data<AppState>, audit: AuditContext, path: web::Path<PathOptions>, if_match: Option<web::Header<IfMatch>>, body: web::Json<UpdateUser>) -> impl Responder {
     let user_id = path.into_inner().id;
 
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
     let query_result = sqlx::query_as!(
         UserModel,
-        "SELECT * FROM users WHERE id = $1",
+        "SELECT * FROM users WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
         user_id
     )
-        .fetch_one(&data.db)
+        .fetch_one(&mut tx)
         .await;
 
     let user = match query_result {
@@ -132,33 +302,45 @@ async fn update_user(data: web::Data<AppState>, path: web::Path<PathOptions>, bo
                 "status": "error",
                 "message": format!("{}", err)
             });
-
             return HttpResponse::InternalServerError().json(json_error);
         }
     };
+    let before = audit::snapshot(&user);
+
+    if !etag::if_match(&if_match, &etag::entity_tag(user.updated_at)) {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("User {} has been modified since it was fetched", user_id)
+        });
+        return HttpResponse::PreconditionFailed().json(json_error);
+    }
+
+    let user = match body.into_inner().merge_into(user) {
+        Ok(user) => user,
+        Err(message) => {
+            let json_error = json!({
+                "status": "error",
+                "message": message
+            });
+            return HttpResponse::BadRequest().json(json_error);
+        }
+    };
 
     let now = Utc::now();
 
     let query_result = sqlx::query_as!(
         UserModel,
-        "UPDATE users SET username = $1, email = $2, updated_at = $3 WHERE id = $4 RETURNING *",
-        body.username.to_owned().unwrap_or(user.username),
-        body.email.to_owned().unwrap_or(user.email.unwrap_or("".to_string())),
+        "UPDATE users SET username = $1, email = $2, email_verified_at = CASE WHEN email IS DISTINCT FROM $2::varchar THEN NULL ELSE email_verified_at END, updated_at = $3 WHERE id = $4 RETURNING *",
+        user.username,
+        user.email,
         now,
         user_id
     )
-        .fetch_one(&data.db)
+        .fetch_one(&mut tx)
         .await;
 
-    match query_result {
-        Ok(user) => {
-            let json_response = json!({
-                "status": "success",
-                "data": user
-            });
-
-            return HttpResponse::Ok().json(json_response);
-        },
+    let user = match query_result {
+        Ok(user) => user,
         Err(err) => {
             let json_error = json!({
                 "status": "error",
@@ -166,21 +348,219 @@ async fn update_user(data: web::Data<AppState>, path: web::Path<PathOptions>, bo
             });
             return HttpResponse::InternalServerError().json(json_error);
         }
+    };
+
+    let query_result = audit::record(&mut tx, &audit, "update", "users", user_id, before, audit::snapshot(&user)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
     }
+
+    let json_response = json!({
+        "status": "success",
+        "data": user
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(user.updated_at))).json(json_response)
 }
 
 
 #[delete("/{id}")]
-async fn delete_user(data: web::Data<AppState>, path: web::Path<PathOptions>) -> impl Responder {
+async fn delete_user(data: web::Data<AppState>, audit: AuditContext, path: web::Path<PathOptions>, if_match: Option<web::Header<IfMatch>>, opts: web::Query<DeleteOptions>) -> impl Responder {
     let user_id = path.into_inner().id;
 
-    let query_result = sqlx::query_scalar!("DELETE FROM users WHERE id = $1", user_id)
-        .execute(&data.db)
-        .await
-        .unwrap()
-        .rows_affected();
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        UserModel,
+        "SELECT * FROM users WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+        user_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let user = match query_result {
+        Ok(user) => user,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    if let Some(user) = &user {
+        if !etag::if_match(&if_match, &etag::entity_tag(user.updated_at)) {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("User {} has been modified since it was fetched", user_id)
+            });
+            return HttpResponse::PreconditionFailed().json(json_error);
+        }
+    }
+
+    let now = Utc::now();
+
+    if user.is_some() {
+        match opts.strategy() {
+            DeleteStrategy::Restrict => {
+                let query_result = sqlx::query_scalar!(
+                    "SELECT id FROM ratings WHERE user_id = $1 AND deleted_at IS NULL",
+                    user_id
+                )
+                    .fetch_all(&mut tx)
+                    .await;
+
+                let ratings = match query_result {
+                    Ok(ids) => ids,
+                    Err(err) => {
+                        let json_error = json!({
+                            "status": "error",
+                            "message": format!("{}", err)
+                        });
+                        return HttpResponse::InternalServerError().json(json_error);
+                    }
+                };
+
+                let query_result = sqlx::query_scalar!(
+                    "SELECT id FROM purchases WHERE user_id = $1 AND deleted_at IS NULL",
+                    user_id
+                )
+                    .fetch_all(&mut tx)
+                    .await;
+
+                let purchases = match query_result {
+                    Ok(ids) => ids,
+                    Err(err) => {
+                        let json_error = json!({
+                            "status": "error",
+                            "message": format!("{}", err)
+                        });
+                        return HttpResponse::InternalServerError().json(json_error);
+                    }
+                };
+
+                if !ratings.is_empty() || !purchases.is_empty() {
+                    let json_error = json!({
+                        "status": "error",
+                        "message": format!("User {} still has {} ratings and {} purchases", user_id, ratings.len(), purchases.len()),
+                        "dependents": {
+                            "ratings": ratings,
+                            "purchases": purchases
+                        }
+                    });
+                    return HttpResponse::Conflict().json(json_error);
+                }
+            },
+            DeleteStrategy::Reassign => {
+                let json_error = json!({
+                    "status": "error",
+                    "message": "Users cannot be reassigned, use strategy=restrict or strategy=cascade"
+                });
+                return HttpResponse::BadRequest().json(json_error);
+            },
+            DeleteStrategy::Cascade => {
+                let query_result = sqlx::query!(
+                    "WITH affected AS (\n                        UPDATE ratings SET deleted_at = $1, updated_at = $1 FROM ratings old\n                        WHERE ratings.id = old.id AND ratings.user_id = $2 AND ratings.deleted_at IS NULL\n                        RETURNING ratings.id, to_jsonb(old) AS before, to_jsonb(ratings) AS after\n                    )\n                    INSERT INTO audit_log (actor, action, resource_type, resource_id, before, after, request_id)\n                    SELECT $3, 'delete', 'ratings', id, before, after, $4 FROM affected",
+                    now,
+                    user_id,
+                    audit.actor,
+                    audit.request_id
+                )
+                    .execute(&mut tx)
+                    .await;
+
+                if let Err(err) = query_result {
+                    let json_error = json!({
+                        "status": "error",
+                        "message": format!("{}", err)
+                    });
+                    return HttpResponse::InternalServerError().json(json_error);
+                }
+
+                let query_result = sqlx::query!(
+                    r#"WITH affected AS (
+                            UPDATE purchases SET deleted_at = $1, updated_at = $1 FROM purchases old
+                            WHERE purchases.id = old.id AND purchases.user_id = $2 AND purchases.deleted_at IS NULL
+                            RETURNING purchases.*, to_jsonb(old) AS before, to_jsonb(purchases) AS after
+                        ), audited AS (
+                            INSERT INTO audit_log (actor, action, resource_type, resource_id, before, after, request_id)
+                            SELECT $3, 'delete', 'purchases', id, before, after, $4 FROM affected
+                        )
+                        SELECT product_id AS "product_id!", warehouse_id AS "warehouse_id!", quantity AS "quantity!", status AS "status!" FROM affected"#,
+                    now,
+                    user_id,
+                    audit.actor,
+                    audit.request_id
+                )
+                    .fetch_all(&mut tx)
+                    .await;
+
+                let deleted = match query_result {
+                    Ok(deleted) => deleted,
+                    Err(err) => {
+                        let json_error = json!({
+                            "status": "error",
+                            "message": format!("{}", err)
+                        });
+                        return HttpResponse::InternalServerError().json(json_error);
+                    }
+                };
+
+                // Deleted purchases no longer hold stock.
+                for purchase in deleted {
+                    if let Err(err) = inventory::release_purchase(&mut tx, &purchase.status, purchase.warehouse_id, purchase.product_id, purchase.quantity).await {
+                        let json_error = json!({
+                            "status": "error",
+                            "message": format!("{}", err)
+                        });
+                        return HttpResponse::InternalServerError().json(json_error);
+                    }
+                }
+            }
+        }
+    }
+
+    let query_result = sqlx::query!(
+        "UPDATE users SET deleted_at = $1, updated_at = $1 WHERE id = $2 AND deleted_at IS NULL",
+        now,
+        user_id
+    )
+        .execute(&mut tx)
+        .await;
+
+    let rows_affected = match query_result {
+        Ok(result) => result.rows_affected(),
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
 
-    if query_result == 0 {
+    if rows_affected == 0 {
         let json_error = json!({
             "status": "not found",
             "message": format!("There is no user with ID: {}", user_id)
@@ -189,20 +569,643 @@ async fn delete_user(data: web::Data<AppState>, path: web::Path<PathOptions>) ->
         return HttpResponse::NotFound().json(json_error);
     }
 
-    let json_response = json!({
-        "status": "success",
-        "message": format!("User removed with ID: {}", user_id)
-    });
-
-    HttpResponse::Ok().json(json_response)
+    let query_result = audit::record(&mut tx, &audit, "delete", "users", user_id, user.as_ref().and_then(audit::snapshot), None).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "message": format!("User removed with ID: {}", user_id)
+    });
+
+    HttpResponse::Ok().json(json_response)
+}
+
+#[get("/trash")]
+async fn get_deleted_users(data: web::Data<AppState>, _admin: Admin, opts: web::Query<FilterOptions>) -> impl Responder {
+    let limit = opts.limit.unwrap_or(10);
+    let offset = opts.offset.unwrap_or(1).saturating_sub(1) * limit;
+
+    let query_result = sqlx::query_as!(
+        UserModel,
+        "SELECT * FROM users WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC LIMIT $1 OFFSET $2",
+        limit as i32,
+        offset as i32
+    )
+        .fetch_all(&data.db)
+        .await;
+
+    let users = match query_result {
+        Ok(users) => users,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_count = sqlx::query_scalar("SELECT COUNT(*) FROM users WHERE deleted_at IS NOT NULL")
+        .fetch_one(&data.db)
+        .await;
+
+    let deleted_count: i64 = match query_count {
+        Ok(count) => count,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+    let json_response = json!({
+        "status": "success",
+        "count": deleted_count,
+        "data": users
+    });
+
+    HttpResponse::Ok().json(json_response)
+}
+
+#[post("/{id}/restore")]
+async fn restore_user(data: web::Data<AppState>, _admin: Admin, audit: AuditContext, path: web::Path<PathOptions>) -> impl Responder {
+    let user_id = path.into_inner().id;
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        UserModel,
+        "SELECT * FROM users WHERE id = $1 AND deleted_at IS NOT NULL FOR UPDATE",
+        user_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let before = match query_result {
+        Ok(Some(user)) => audit::snapshot(&user),
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no deleted user with ID: {}", user_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let now = Utc::now();
+
+    let query_result = sqlx::query_as!(
+        UserModel,
+        "UPDATE users SET deleted_at = NULL, updated_at = $1 WHERE id = $2 RETURNING *",
+        now,
+        user_id
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let user = match query_result {
+        Ok(user) => user,
+        Err(sqlx::Error::Database(err)) if err.code().as_deref() == Some("23505") => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("Cannot restore user {}: {}", user_id, err)
+            });
+            return HttpResponse::Conflict().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = audit::record(&mut tx, &audit, "restore", "users", user_id, before, audit::snapshot(&user)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": user
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(user.updated_at))).json(json_response)
+}
+
+#[get("/{id}/orders")]
+async fn get_user_orders(data: web::Data<AppState>, path: web::Path<PathOptions>, opts: web::Query<FilterOptions>) -> impl Responder {
+    let user_id = path.into_inner().id;
+    let limit = opts.limit.unwrap_or(10);
+    let offset = opts.offset.unwrap_or(1).saturating_sub(1) * limit;
+
+    let query_result = sqlx::query_as!(
+        OrderModel,
+        "SELECT * FROM orders WHERE user_id = $1 ORDER BY created_at DESC LIMIT $2 OFFSET $3",
+        user_id,
+        limit as i32,
+        offset as i32
+    )
+        .fetch_all(&data.db)
+        .await;
+
+    let orders = match query_result {
+        Ok(orders) => orders,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_count = sqlx::query_scalar("SELECT COUNT(*) FROM orders WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_one(&data.db)
+        .await;
+
+    let orders_count: i64 = match query_count {
+        Ok(count) => count,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let orders = match orders::with_items(&data.db, orders).await {
+        Ok(orders) => orders,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let json_response = json!({
+        "status": "success",
+        "count": orders_count,
+        "data": orders
+    });
+
+    HttpResponse::Ok().json(json_response)
+}
+
+/// Issues an email verification token. There is no mailer in this service,
+/// so the token is handed to the admin caller for delivery.
+#[post("/{id}/email-verification")]
+async fn request_email_verification(data: web::Data<AppState>, _admin: Admin, path: web::Path<PathOptions>) -> impl Responder {
+    let user_id = path.into_inner().id;
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        UserModel,
+        "SELECT * FROM users WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+        user_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let user = match query_result {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no user with ID: {}", user_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let email = match user.email {
+        Some(email) => email,
+        None => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("User {} has no email address", user_id)
+            });
+            return HttpResponse::UnprocessableEntity().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_scalar!(
+        "INSERT INTO email_verifications (user_id, email) VALUES ($1, $2) RETURNING token",
+        user_id,
+        email
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let token = match query_result {
+        Ok(token) => token,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": {
+            "user_id": user_id,
+            "email": email,
+            "token": token
+        }
+    });
+    HttpResponse::Ok().json(json_response)
+}
+
+/// Marks the email as verified and attaches every unclaimed guest purchase
+/// made with that address to the user.
+#[post("/{id}/email-verification/confirm")]
+async fn confirm_email_verification(data: web::Data<AppState>, audit: AuditContext, path: web::Path<PathOptions>, body: web::Json<ConfirmEmail>) -> impl Responder {
+    let user_id = path.into_inner().id;
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        UserModel,
+        "SELECT * FROM users WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+        user_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let user = match query_result {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no user with ID: {}", user_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let before = audit::snapshot(&user);
+
+    // A token only counts for the address it was issued for.
+    let query_result = sqlx::query_scalar!(
+        "SELECT token FROM email_verifications WHERE token = $1 AND user_id = $2 AND email = $3",
+        body.token,
+        user_id,
+        user.email
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    match query_result {
+        Ok(Some(_)) => {},
+        Ok(None) => {
+            let json_error = json!({
+                "status": "error",
+                "message": "Invalid or outdated verification token"
+            });
+            return HttpResponse::UnprocessableEntity().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    }
+
+    let now = Utc::now();
+
+    let query_result = sqlx::query_as!(
+        UserModel,
+        "UPDATE users SET email_verified_at = $1, updated_at = $1 WHERE id = $2 RETURNING *",
+        now,
+        user_id
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let user = match query_result {
+        Ok(user) => user,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query!("DELETE FROM email_verifications WHERE user_id = $1", user_id)
+        .execute(&mut tx)
+        .await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let query_result = audit::record(&mut tx, &audit, "update", "users", user_id, before, audit::snapshot(&user)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let query_result = sqlx::query_scalar!(
+        r#"WITH affected AS (
+                UPDATE purchases SET user_id = $1, claim_token = NULL, updated_at = $2 FROM purchases old
+                WHERE purchases.id = old.id AND purchases.user_id IS NULL AND LOWER(purchases.guest_email) = LOWER($3)
+                RETURNING purchases.id, to_jsonb(old) - 'claim_token' AS before, to_jsonb(purchases) - 'claim_token' AS after
+            ), logged AS (
+                INSERT INTO audit_log (actor, action, resource_type, resource_id, before, after, request_id)
+                SELECT $4, 'update', 'purchases', id, before, after, $5 FROM affected
+            )
+            SELECT COUNT(*) AS "count!" FROM affected"#,
+        user_id,
+        now,
+        user.email,
+        audit.actor,
+        audit.request_id
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let claimed = match query_result {
+        Ok(claimed) => claimed,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": user,
+        "claimed_purchases": claimed
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(user.updated_at))).json(json_response)
+}
+
+/// Attaches a single guest purchase to a verified user; the claim token
+/// proves the caller made it, and the purchase must have been made with the
+/// user's verified email.
+#[post("/{id}/claims")]
+async fn claim_purchase(data: web::Data<AppState>, audit: AuditContext, user: CurrentUser, path: web::Path<PathOptions>, body: web::Json<ClaimPurchase>) -> impl Responder {
+    let user_id = path.into_inner().id;
+
+    if !user.is(user_id) {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("Only user {} can claim purchases for their account", user_id)
+        });
+        return HttpResponse::Forbidden().json(json_error);
+    }
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        UserModel,
+        "SELECT * FROM users WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+        user_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let user = match query_result {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no user with ID: {}", user_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    if user.email_verified_at.is_none() {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("User {} must verify their email before claiming purchases", user_id)
+        });
+        return HttpResponse::Forbidden().json(json_error);
+    }
+
+    let query_result = sqlx::query_as!(
+        PurchaseModel,
+        "SELECT * FROM purchases WHERE claim_token = $1 AND user_id IS NULL AND deleted_at IS NULL FOR UPDATE",
+        body.claim_token
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let purchase = match query_result {
+        Ok(Some(purchase)) => purchase,
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": "There is no unclaimed purchase for this claim token"
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+    let before = audit::snapshot(&purchase);
+
+    let query_result = sqlx::query_as!(
+        PurchaseModel,
+        r#"UPDATE purchases SET user_id = users.id, claim_token = NULL, updated_at = $2 FROM users
+            WHERE purchases.id = $3 AND users.id = $1 AND LOWER(purchases.guest_email) = LOWER(users.email)
+            RETURNING purchases.*"#,
+        user_id,
+        Utc::now(),
+        purchase.id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let purchase = match query_result {
+        Ok(Some(purchase)) => purchase,
+        Ok(None) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("Purchase {} was not made with the email of user {}", purchase.id, user_id)
+            });
+            return HttpResponse::Forbidden().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = audit::record(&mut tx, &audit, "update", "purchases", purchase.id, before, audit::snapshot(&purchase)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": purchase
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(purchase.updated_at))).json(json_response)
 }
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     let scope = web::scope("/users")
         .service(get_users)
+        .service(get_deleted_users)
         .service(create_user)
         .service(get_user)
+        .service(get_user_orders)
+        .service(request_email_verification)
+        .service(confirm_email_verification)
+        .service(claim_purchase)
+        .service(json_patch_user)
         .service(update_user)
-        .service(delete_user);
+        .service(delete_user)
+        .service(restore_user);
     cfg.service(scope);
 }
\ No newline at end of file
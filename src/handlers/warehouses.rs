@@ -0,0 +1,290 @@
+use actix_web::{get, post, patch, web, Responder, HttpResponse, http::header::{ETag, IfMatch}};
+use serde_json::json;
+use chrono::Utc;
+
+use crate::{AppState, audit::{self, AuditContext}, auth::Admin, etag, schema::PathOptions, models::warehouses::{WarehouseModel, CreateWarehouse, UpdateWarehouse}};
+
+/// Rejects coordinates outside the valid latitude/longitude ranges.
+fn validate_location(latitude: f64, longitude: f64) -> Result<(), String> {
+    if !(-90.0..=90.0).contains(&latitude) {
+        return Err("latitude must be between -90 and 90".to_string());
+    }
+    if !(-180.0..=180.0).contains(&longitude) {
+        return Err("longitude must be between -180 and 180".to_string());
+    }
+    Ok(())
+}
+
+#[get("")]
+async fn get_warehouses(data: web::Data<AppState>) -> impl Responder {
+    let query_result = sqlx::query_as!(
+        WarehouseModel,
+        "SELECT * FROM warehouses ORDER BY warehouse_name"
+    )
+        .fetch_all(&data.db)
+        .await;
+
+    let warehouses = match query_result {
+        Ok(warehouses) => warehouses,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let json_response = json!({
+        "status": "success",
+        "count": warehouses.len(),
+        "data": warehouses
+    });
+    HttpResponse::Ok().json(json_response)
+}
+
+#[post("")]
+async fn create_warehouse(data: web::Data<AppState>, _admin: Admin, audit: AuditContext, body: web::Json<CreateWarehouse>) -> impl Responder {
+    if let Err(message) = validate_location(body.latitude, body.longitude) {
+        let json_error = json!({
+            "status": "error",
+            "message": message
+        });
+        return HttpResponse::BadRequest().json(json_error);
+    }
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        WarehouseModel,
+        "INSERT INTO warehouses (warehouse_name, latitude, longitude) VALUES ($1, $2, $3) RETURNING *",
+        body.warehouse_name,
+        body.latitude,
+        body.longitude
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let warehouse = match query_result {
+        Ok(warehouse) => warehouse,
+        Err(sqlx::Error::Database(err)) if err.code().as_deref() == Some("23505") => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("A warehouse named {} already exists", body.warehouse_name)
+            });
+            return HttpResponse::Conflict().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = audit::record(&mut tx, &audit, "create", "warehouses", warehouse.id, None, audit::snapshot(&warehouse)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": warehouse
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(warehouse.updated_at))).json(json_response)
+}
+
+#[get("/{id}")]
+async fn get_warehouse(data: web::Data<AppState>, path: web::Path<PathOptions>) -> impl Responder {
+    let warehouse_id = path.into_inner().id;
+
+    let query_result = sqlx::query_as!(
+        WarehouseModel,
+        "SELECT * FROM warehouses WHERE id = $1",
+        warehouse_id
+    )
+        .fetch_optional(&data.db)
+        .await;
+
+    let warehouse = match query_result {
+        Ok(Some(warehouse)) => warehouse,
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no warehouse with ID: {}", warehouse_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let etag = etag::entity_tag(warehouse.updated_at);
+    let json_response = json!({
+        "status": "success",
+        "data": warehouse
+    });
+    HttpResponse::Ok().insert_header(ETag(etag)).json(json_response)
+}
+
+#[patch("/{id}")]
+async fn update_warehouse(data: web::Data<AppState>, _admin: Admin, audit: AuditContext, path: web::Path<PathOptions>, if_match: Option<web::Header<IfMatch>>, body: web::Json<UpdateWarehouse>) -> impl Responder {
+    let warehouse_id = path.into_inner().id;
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = sqlx::query_as!(
+        WarehouseModel,
+        "SELECT * FROM warehouses WHERE id = $1 FOR UPDATE",
+        warehouse_id
+    )
+        .fetch_optional(&mut tx)
+        .await;
+
+    let warehouse = match query_result {
+        Ok(Some(warehouse)) => warehouse,
+        Ok(None) => {
+            let json_error = json!({
+                "status": "not found",
+                "message": format!("There is no warehouse with ID: {}", warehouse_id)
+            });
+            return HttpResponse::NotFound().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+    let before = audit::snapshot(&warehouse);
+
+    if !etag::if_match(&if_match, &etag::entity_tag(warehouse.updated_at)) {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("Warehouse {} has been modified since it was fetched", warehouse_id)
+        });
+        return HttpResponse::PreconditionFailed().json(json_error);
+    }
+
+    let warehouse = match body.into_inner().merge_into(warehouse) {
+        Ok(warehouse) => warehouse,
+        Err(message) => {
+            let json_error = json!({
+                "status": "error",
+                "message": message
+            });
+            return HttpResponse::BadRequest().json(json_error);
+        }
+    };
+
+    if let Err(message) = validate_location(warehouse.latitude, warehouse.longitude) {
+        let json_error = json!({
+            "status": "error",
+            "message": message
+        });
+        return HttpResponse::BadRequest().json(json_error);
+    }
+
+    let now = Utc::now();
+
+    let query_result = sqlx::query_as!(
+        WarehouseModel,
+        "UPDATE warehouses SET warehouse_name = $1, latitude = $2, longitude = $3, updated_at = $4 WHERE id = $5 RETURNING *",
+        warehouse.warehouse_name,
+        warehouse.latitude,
+        warehouse.longitude,
+        now,
+        warehouse_id
+    )
+        .fetch_one(&mut tx)
+        .await;
+
+    let warehouse = match query_result {
+        Ok(warehouse) => warehouse,
+        Err(sqlx::Error::Database(err)) if err.code().as_deref() == Some("23505") => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("Cannot rename warehouse {}: {}", warehouse_id, err)
+            });
+            return HttpResponse::Conflict().json(json_error);
+        },
+        Err(err) => {
+            let json_error = json!({
+                "status": "error",
+                "message": format!("{}", err)
+            });
+            return HttpResponse::InternalServerError().json(json_error);
+        }
+    };
+
+    let query_result = audit::record(&mut tx, &audit, "update", "warehouses", warehouse_id, before, audit::snapshot(&warehouse)).await;
+    if let Err(err) = query_result {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    if let Err(err) = tx.commit().await {
+        let json_error = json!({
+            "status": "error",
+            "message": format!("{}", err)
+        });
+        return HttpResponse::InternalServerError().json(json_error);
+    }
+
+    let json_response = json!({
+        "status": "success",
+        "data": warehouse
+    });
+    HttpResponse::Ok().insert_header(ETag(etag::entity_tag(warehouse.updated_at))).json(json_response)
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    let scope = web::scope("/warehouses")
+        .service(get_warehouses)
+        .service(create_warehouse)
+        .service(get_warehouse)
+        .service(update_warehouse);
+
+    cfg.service(scope);
+}
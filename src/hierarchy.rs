@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use actix_web::http::StatusCode;
+use sqlx::{PgExecutor, Postgres, Transaction};
+use uuid::Uuid;
+use crate::models::categories::{CategoryModel, CategoryNode};
+
+
+/// Serializes changes to category parents, so two concurrent moves cannot
+/// together close a cycle that neither would on its own.
+pub async fn lock(tx: &mut Transaction<'_, Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query!("SELECT pg_advisory_xact_lock(hashtext('categories.parent_id'))")
+        .execute(tx)
+        .await?;
+
+    Ok(())
+}
+
+/// Ids of a category and every category below it. `UNION` rather than
+/// `UNION ALL` keeps the walk finite even if the data already holds a cycle.
+pub async fn subtree_ids<'e, E>(executor: E, category_id: Uuid) -> Result<Vec<Uuid>, sqlx::Error>
+where
+    E: PgExecutor<'e>
+{
+    sqlx::query_scalar!(
+        r#"WITH RECURSIVE subtree(id) AS (
+                SELECT $1::uuid
+                UNION
+                SELECT c.id FROM categories c JOIN subtree s ON c.parent_id = s.id WHERE c.deleted_at IS NULL
+            )
+            SELECT id AS "id!" FROM subtree"#,
+        category_id
+    )
+        .fetch_all(executor)
+        .await
+}
+
+/// Whether making `parent_id` the parent of `category_id` would put the
+/// category below itself.
+pub async fn would_cycle<'e, E>(executor: E, category_id: Uuid, parent_id: Uuid) -> Result<bool, sqlx::Error>
+where
+    E: PgExecutor<'e>
+{
+    let subtree = subtree_ids(executor, category_id).await?;
+
+    Ok(subtree.contains(&parent_id))
+}
+
+/// Checks that `parent_id` can become the parent of `category_id`: the
+/// parent must be a live category and must not sit below the category. Takes
+/// the hierarchy lock first, so the answer holds until the transaction ends.
+/// Errors come back as the status and message to respond with.
+pub async fn validate_parent(tx: &mut Transaction<'_, Postgres>, category_id: Uuid, parent_id: Uuid) -> Result<(), (StatusCode, String)> {
+    let internal = |err: sqlx::Error| (StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err));
+
+    lock(&mut *tx).await.map_err(internal)?;
+
+    let parent = sqlx::query_scalar!(
+        "SELECT id FROM categories WHERE id = $1 AND deleted_at IS NULL FOR SHARE",
+        parent_id
+    )
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(internal)?;
+
+    if parent.is_none() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, format!("There is no category with ID: {}", parent_id)));
+    }
+
+    if would_cycle(&mut *tx, category_id, parent_id).await.map_err(internal)? {
+        return Err((StatusCode::CONFLICT, format!("Category {} cannot be moved under itself or one of its descendants", category_id)));
+    }
+
+    Ok(())
+}
+
+/// Nests categories under their parents. Categories whose parent is not in
+/// the list become roots; siblings keep the order they were given in. A cycle
+/// in the data is broken at its first member in the given order, which also
+/// becomes a root, so every category appears exactly once.
+pub fn build_tree(categories: Vec<CategoryModel>) -> Vec<CategoryNode> {
+    let ids: Vec<Uuid> = categories.iter().map(|category| category.id).collect();
+    let mut placement = Vec::with_capacity(categories.len());
+    let mut children: HashMap<Option<Uuid>, Vec<CategoryModel>> = HashMap::new();
+    for category in categories {
+        let parent_id = category.parent_id.filter(|parent_id| ids.contains(parent_id));
+        placement.push((category.id, parent_id));
+        children.entry(parent_id).or_default().push(category);
+    }
+
+    fn attach(parent_id: Option<Uuid>, children: &mut HashMap<Option<Uuid>, Vec<CategoryModel>>) -> Vec<CategoryNode> {
+        children
+            .remove(&parent_id)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|category| {
+                let id = category.id;
+                CategoryNode { category, children: attach(Some(id), children) }
+            })
+            .collect()
+    }
+
+    let mut roots = attach(None, &mut children);
+
+    // Whatever is left was never reached from a root: it sits on a cycle or below one.
+    for (id, parent_id) in placement {
+        let Some(siblings) = children.get_mut(&parent_id) else { continue };
+        let Some(position) = siblings.iter().position(|category| category.id == id) else { continue };
+        let category = siblings.remove(position);
+        roots.push(CategoryNode { category, children: attach(Some(id), &mut children) });
+    }
+
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn category(id: u128, parent_id: Option<u128>) -> CategoryModel {
+        CategoryModel {
+            id: Uuid::from_u128(id),
+            category_name: format!("category {}", id),
+            created_at: None,
+            updated_at: None,
+            deleted_at: None,
+            parent_id: parent_id.map(Uuid::from_u128)
+        }
+    }
+
+    fn shape(nodes: &[CategoryNode]) -> Vec<(u128, Vec<u128>)> {
+        nodes
+            .iter()
+            .map(|node| (node.category.id.as_u128(), node.children.iter().map(|child| child.category.id.as_u128()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn nests_children_under_their_parents() {
+        let tree = build_tree(vec![category(1, None), category(2, Some(1)), category(3, Some(1))]);
+
+        assert_eq!(shape(&tree), vec![(1, vec![2, 3])]);
+    }
+
+    #[test]
+    fn orphan_becomes_a_root() {
+        let tree = build_tree(vec![category(1, None), category(2, Some(99)), category(3, Some(2))]);
+
+        assert_eq!(shape(&tree), vec![(1, vec![]), (2, vec![3])]);
+    }
+
+    #[test]
+    fn cycle_is_broken_at_its_first_member() {
+        let tree = build_tree(vec![category(1, None), category(2, Some(3)), category(3, Some(2)), category(4, Some(3))]);
+
+        assert_eq!(shape(&tree), vec![(1, vec![]), (2, vec![3])]);
+        assert_eq!(shape(&tree[1].children), vec![(3, vec![4])]);
+    }
+
+    #[test]
+    fn self_parent_becomes_a_root() {
+        let tree = build_tree(vec![category(1, Some(1))]);
+
+        assert_eq!(shape(&tree), vec![(1, vec![])]);
+    }
+}
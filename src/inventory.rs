@@ -0,0 +1,258 @@
+use std::str::FromStr;
+use chrono::Utc;
+use sqlx::{PgExecutor, Postgres, Transaction};
+use uuid::Uuid;
+use crate::models::{products::StockAdjustmentModel, purchases::PurchaseStatus, warehouses::Location};
+
+
+/// How a purchase picks the warehouse it is fulfilled from. Only warehouses
+/// holding the full quantity are considered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FulfilmentStrategy {
+    /// Closest to the delivery address; falls back to `MostStock` when the
+    /// purchase has no address.
+    Nearest,
+    MostStock
+}
+
+impl FromStr for FulfilmentStrategy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "nearest" => Ok(FulfilmentStrategy::Nearest),
+            "most_stock" => Ok(FulfilmentStrategy::MostStock),
+            _ => Err(format!("unknown fulfilment strategy: {}", value))
+        }
+    }
+}
+
+/// Great-circle distance in kilometres.
+fn distance_km(from: &Location, latitude: f64, longitude: f64) -> f64 {
+    let (lat1, lat2) = (from.latitude.to_radians(), latitude.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (longitude - from.longitude).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * 6371.0 * a.sqrt().asin()
+}
+
+/// Locks a product row. Every stock change takes this lock before touching
+/// `warehouse_stock`, the same order as the handlers that lock the product
+/// up front, so two stock changes on one product cannot deadlock.
+async fn lock_product(tx: &mut Transaction<'_, Postgres>, product_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!("SELECT id FROM products WHERE id = $1 FOR UPDATE", product_id)
+        .fetch_optional(tx)
+        .await?;
+
+    Ok(())
+}
+
+/// Picks the warehouse to fulfil `quantity` units of a product from, locking
+/// its stock row. `None` means no single warehouse holds enough.
+pub async fn pick_warehouse(
+    tx: &mut Transaction<'_, Postgres>,
+    product_id: Uuid,
+    quantity: i32,
+    strategy: FulfilmentStrategy,
+    ship_to: Option<&Location>
+) -> Result<Option<Uuid>, sqlx::Error> {
+    lock_product(&mut *tx, product_id).await?;
+
+    let candidates = sqlx::query!(
+        "SELECT w.id, w.latitude, w.longitude, s.quantity
+            FROM warehouse_stock s JOIN warehouses w ON w.id = s.warehouse_id
+            WHERE s.product_id = $1 AND s.quantity >= $2
+            ORDER BY w.id
+            FOR UPDATE OF s",
+        product_id,
+        quantity
+    )
+        .fetch_all(tx)
+        .await?;
+
+    let picked = match (strategy, ship_to) {
+        (FulfilmentStrategy::Nearest, Some(ship_to)) => candidates
+            .iter()
+            .min_by(|a, b| {
+                distance_km(ship_to, a.latitude, a.longitude).total_cmp(&distance_km(ship_to, b.latitude, b.longitude))
+            }),
+        _ => candidates.iter().max_by_key(|candidate| candidate.quantity)
+    };
+
+    Ok(picked.map(|candidate| candidate.id))
+}
+
+/// Picks a warehouse and takes `quantity` units out of it. `None` means no
+/// single warehouse can fulfil the quantity and nothing was taken.
+pub async fn fulfil(
+    tx: &mut Transaction<'_, Postgres>,
+    product_id: Uuid,
+    quantity: i32,
+    strategy: FulfilmentStrategy,
+    ship_to: Option<&Location>
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let warehouse_id = match pick_warehouse(&mut *tx, product_id, quantity, strategy, ship_to).await? {
+        Some(warehouse_id) => warehouse_id,
+        None => return Ok(None)
+    };
+
+    let taken = take(tx, warehouse_id, product_id, quantity).await?;
+    Ok(taken.map(|_| warehouse_id))
+}
+
+/// Takes `quantity` units out of one warehouse and the product total. The
+/// update is conditional, so stock never goes negative; `None` means the
+/// warehouse does not have enough units left. Returns the warehouse level.
+pub async fn take(tx: &mut Transaction<'_, Postgres>, warehouse_id: Uuid, product_id: Uuid, quantity: i32) -> Result<Option<i32>, sqlx::Error> {
+    lock_product(&mut *tx, product_id).await?;
+
+    let remaining = sqlx::query_scalar!(
+        "UPDATE warehouse_stock SET quantity = quantity - $3
+            WHERE warehouse_id = $1 AND product_id = $2 AND quantity >= $3
+            RETURNING quantity",
+        warehouse_id,
+        product_id,
+        quantity
+    )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+    if remaining.is_some() {
+        sqlx::query!(
+            "UPDATE products SET stock_quantity = stock_quantity - $2, updated_at = $3 WHERE id = $1",
+            product_id,
+            quantity,
+            Utc::now()
+        )
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    Ok(remaining)
+}
+
+/// Puts units back into a warehouse, e.g. when a purchase is cancelled or
+/// moved to another product. Returns the warehouse level.
+pub async fn release(tx: &mut Transaction<'_, Postgres>, warehouse_id: Uuid, product_id: Uuid, quantity: i32) -> Result<i32, sqlx::Error> {
+    lock_product(&mut *tx, product_id).await?;
+
+    let level = sqlx::query_scalar!(
+        "INSERT INTO warehouse_stock (warehouse_id, product_id, quantity) VALUES ($1, $2, $3)
+            ON CONFLICT (warehouse_id, product_id) DO UPDATE SET quantity = warehouse_stock.quantity + EXCLUDED.quantity
+            RETURNING quantity",
+        warehouse_id,
+        product_id,
+        quantity
+    )
+        .fetch_one(&mut *tx)
+        .await?;
+
+    sqlx::query!(
+        "UPDATE products SET stock_quantity = stock_quantity + $2, updated_at = $3 WHERE id = $1",
+        product_id,
+        quantity,
+        Utc::now()
+    )
+        .execute(&mut *tx)
+        .await?;
+
+    Ok(level)
+}
+
+/// Returns the units of a purchase that is being deleted to its warehouse.
+/// Cancelled purchases gave theirs back when they were cancelled.
+pub async fn release_purchase(tx: &mut Transaction<'_, Postgres>, status: &str, warehouse_id: Uuid, product_id: Uuid, quantity: i32) -> Result<(), sqlx::Error> {
+    if status == PurchaseStatus::Cancelled.as_str() {
+        return Ok(());
+    }
+
+    release(tx, warehouse_id, product_id, quantity).await?;
+    Ok(())
+}
+
+/// Moves a purchase's booking from `previous` to `next`, each a
+/// `(product_id, quantity)` pair: the previous units go back to
+/// `warehouse_id`, then the new ones are fulfilled. `None` means the new
+/// quantity cannot be fulfilled; the caller rolls back, undoing the release.
+pub async fn rebook(
+    tx: &mut Transaction<'_, Postgres>,
+    warehouse_id: Uuid,
+    previous: (Uuid, i32),
+    next: (Uuid, i32),
+    strategy: FulfilmentStrategy
+) -> Result<Option<Uuid>, sqlx::Error> {
+    // Both products are locked in id order before either is touched.
+    let (first, second) = if previous.0 <= next.0 { (previous.0, next.0) } else { (next.0, previous.0) };
+    lock_product(&mut *tx, first).await?;
+    lock_product(&mut *tx, second).await?;
+
+    release(&mut *tx, warehouse_id, previous.0, previous.1).await?;
+    fulfil(tx, next.0, next.1, strategy, None).await
+}
+
+/// Units on hand across all warehouses for a live product, or `None` if
+/// there is no such product.
+pub async fn on_hand<'e, E>(executor: E, product_id: Uuid) -> Result<Option<i32>, sqlx::Error>
+where
+    E: PgExecutor<'e>
+{
+    sqlx::query_scalar!(
+        "SELECT stock_quantity FROM products WHERE id = $1 AND deleted_at IS NULL",
+        product_id
+    )
+        .fetch_optional(executor)
+        .await
+}
+
+/// Units of a product held at one warehouse.
+pub async fn level<'e, E>(executor: E, warehouse_id: Uuid, product_id: Uuid) -> Result<i32, sqlx::Error>
+where
+    E: PgExecutor<'e>
+{
+    let level = sqlx::query_scalar!(
+        "SELECT quantity FROM warehouse_stock WHERE warehouse_id = $1 AND product_id = $2",
+        warehouse_id,
+        product_id
+    )
+        .fetch_optional(executor)
+        .await?;
+
+    Ok(level.unwrap_or(0))
+}
+
+/// Whether a warehouse exists.
+pub async fn warehouse_exists<'e, E>(executor: E, warehouse_id: Uuid) -> Result<bool, sqlx::Error>
+where
+    E: PgExecutor<'e>
+{
+    let found = sqlx::query_scalar!("SELECT id FROM warehouses WHERE id = $1", warehouse_id)
+        .fetch_optional(executor)
+        .await?;
+
+    Ok(found.is_some())
+}
+
+/// Appends a manual correction to the stock ledger. The warehouse level has
+/// already been moved to `quantity_after` in the same transaction.
+pub async fn record_adjustment(
+    tx: &mut Transaction<'_, Postgres>,
+    warehouse_id: Uuid,
+    product_id: Uuid,
+    delta: i32,
+    quantity_after: i32,
+    reason: &str,
+    actor: &str
+) -> Result<StockAdjustmentModel, sqlx::Error> {
+    sqlx::query_as!(
+        StockAdjustmentModel,
+        "INSERT INTO stock_adjustments (warehouse_id, product_id, delta, quantity_after, reason, actor) VALUES ($1, $2, $3, $4, $5, $6) RETURNING *",
+        warehouse_id,
+        product_id,
+        delta,
+        quantity_after,
+        reason,
+        actor
+    )
+        .fetch_one(tx)
+        .await
+}
@@ -0,0 +1,146 @@
+use std::time::Duration;
+
+use actix_web::rt;
+use chrono::Utc;
+use sqlx::{Pool, Postgres};
+
+use crate::inventory;
+
+
+/// Hard-deletes rows that have been soft-deleted for longer than `retention`.
+///
+/// Dependents go first, and parents still referenced by live rows are kept
+/// until those references are gone. Products with a stock ledger are never
+/// purged, so the ledger outlives them, and categories wait for their
+/// subcategories so none is silently moved to the top level.
+pub async fn purge_deleted(db: &Pool<Postgres>, retention: chrono::Duration) -> Result<u64, sqlx::Error> {
+    let cutoff = Utc::now() - retention;
+    let mut tx = db.begin().await?;
+    let mut purged = 0;
+
+    purged += sqlx::query!("DELETE FROM ratings WHERE deleted_at < $1", cutoff)
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+    purged += sqlx::query!("DELETE FROM purchases WHERE deleted_at < $1", cutoff)
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+    purged += sqlx::query!(
+        "DELETE FROM products p WHERE deleted_at < $1
+            AND NOT EXISTS (SELECT 1 FROM ratings WHERE product_id = p.id)
+            AND NOT EXISTS (SELECT 1 FROM purchases WHERE product_id = p.id)
+            AND NOT EXISTS (SELECT 1 FROM order_items WHERE product_id = p.id)
+            AND NOT EXISTS (SELECT 1 FROM stock_adjustments WHERE product_id = p.id)
+            AND NOT EXISTS (SELECT 1 FROM stock_transfers WHERE product_id = p.id)",
+        cutoff
+    )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+    purged += sqlx::query!(
+        "DELETE FROM categories c WHERE deleted_at < $1
+            AND NOT EXISTS (SELECT 1 FROM products WHERE category_id = c.id)
+            AND NOT EXISTS (SELECT 1 FROM categories child WHERE child.parent_id = c.id)",
+        cutoff
+    )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+    purged += sqlx::query!(
+        "DELETE FROM users u WHERE deleted_at < $1
+            AND NOT EXISTS (SELECT 1 FROM ratings WHERE user_id = u.id)
+            AND NOT EXISTS (SELECT 1 FROM purchases WHERE user_id = u.id)
+            AND NOT EXISTS (SELECT 1 FROM orders WHERE user_id = u.id)",
+        cutoff
+    )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+
+    tx.commit().await?;
+    Ok(purged)
+}
+
+/// Runs [`purge_deleted`] every `interval` for the lifetime of the server.
+pub fn spawn_purge(db: Pool<Postgres>, retention: chrono::Duration, interval: Duration) {
+    rt::spawn(async move {
+        let mut ticker = rt::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match purge_deleted(&db, retention).await {
+                Ok(0) => {},
+                Ok(purged) => println!("Purged {} soft-deleted rows", purged),
+                Err(err) => println!("error in purging soft-deleted rows: {}", err)
+            }
+        }
+    });
+}
+
+/// Returns stock held by expired cart reservations. The cart lines stay in
+/// place, unreserved; rows locked by a concurrent checkout are skipped.
+pub async fn release_expired_reservations(db: &Pool<Postgres>) -> Result<u64, sqlx::Error> {
+    let mut tx = db.begin().await?;
+
+    let expired = sqlx::query!(
+        r#"SELECT id, product_id, quantity, warehouse_id AS "warehouse_id!" FROM cart_items
+            WHERE reserved_until <= $1
+            ORDER BY product_id
+            FOR UPDATE SKIP LOCKED"#,
+        Utc::now()
+    )
+        .fetch_all(&mut tx)
+        .await?;
+
+    for item in &expired {
+        inventory::release(&mut tx, item.warehouse_id, item.product_id, item.quantity).await?;
+    }
+
+    let ids: Vec<_> = expired.iter().map(|item| item.id).collect();
+    sqlx::query!(
+        "UPDATE cart_items SET warehouse_id = NULL, reserved_until = NULL WHERE id = ANY($1)",
+        &ids
+    )
+        .execute(&mut tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(expired.len() as u64)
+}
+
+/// Recomputes the `product_rating_stats` view without blocking readers.
+pub async fn refresh_rating_stats(db: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query!("REFRESH MATERIALIZED VIEW CONCURRENTLY product_rating_stats")
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Runs [`refresh_rating_stats`] every `interval` for the lifetime of the server.
+pub fn spawn_rating_stats_refresher(db: Pool<Postgres>, interval: Duration) {
+    rt::spawn(async move {
+        let mut ticker = rt::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = refresh_rating_stats(&db).await {
+                println!("error in refreshing rating statistics: {}", err);
+            }
+        }
+    });
+}
+
+/// Runs [`release_expired_reservations`] every `interval` for the lifetime of the server.
+pub fn spawn_reservation_sweeper(db: Pool<Postgres>, interval: Duration) {
+    rt::spawn(async move {
+        let mut ticker = rt::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match release_expired_reservations(&db).await {
+                Ok(0) => {},
+                Ok(released) => println!("Released {} expired cart reservations", released),
+                Err(err) => println!("error in releasing cart reservations: {}", err)
+            }
+        }
+    });
+}
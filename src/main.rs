@@ -1,5 +1,16 @@
+mod audit;
+mod auth;
+mod config;
+mod etag;
+mod extractors;
+mod hierarchy;
+mod inventory;
 mod models;
+mod moderation;
+mod patching;
+mod pricing;
 mod handlers;
+mod jobs;
 mod schema;
 
 
@@ -7,12 +18,14 @@ use actix_cors::Cors;
 use actix_web::http::header;
 use actix_web::middleware::Logger;
 use actix_web::{HttpServer, App, web};
+use config::Config;
 use dotenv::dotenv;
 use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
 
 
 pub struct AppState {
-    db: Pool<Postgres>
+    db: Pool<Postgres>,
+    config: Config
 }
 
 #[actix_web::main]
@@ -23,10 +36,10 @@ async fn main() -> std::io::Result<()> {
     dotenv().ok();
     env_logger::init();
 
-    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let config = Config::init();
     let pool = match PgPoolOptions::new()
         .max_connections(10)
-        .connect(&database_url)
+        .connect(&config.database_url)
         .await
     {
         Ok(pool) => {
@@ -39,25 +52,56 @@ async fn main() -> std::io::Result<()> {
         }
     };  
 
+    jobs::spawn_purge(
+        pool.clone(),
+        chrono::Duration::days(config.retention_days),
+        std::time::Duration::from_secs(config.purge_interval_secs)
+    );
+    jobs::spawn_reservation_sweeper(
+        pool.clone(),
+        std::time::Duration::from_secs(config.reservation_sweep_interval_secs)
+    );
+    jobs::spawn_rating_stats_refresher(
+        pool.clone(),
+        std::time::Duration::from_secs(config.rating_stats_refresh_secs)
+    );
 
     HttpServer::new(move || {
         let cors = Cors::default()
             .allowed_origin("http://localhost:3000")
-            .allowed_methods(vec!["GET", "POST", "PATCH", "DELETE"])
+            .allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE"])
             .allowed_headers(vec![
                 header::CONTENT_TYPE,
                 header::AUTHORIZATION,
-                header::ACCEPT
-            ]);
+                header::ACCEPT,
+                header::IF_MATCH,
+                header::IF_NONE_MATCH,
+                header::IF_MODIFIED_SINCE,
+                audit::X_REQUEST_ID,
+                audit::X_USER_ID
+            ])
+            .expose_headers(vec![header::ETAG, header::LAST_MODIFIED, audit::X_REQUEST_ID]);
         App::new()
             .app_data(web::Data::new(AppState {
-                db: pool.clone()
+                db: pool.clone(),
+                config: config.clone()
             }))
+            .app_data(extractors::json_config(config.json_limit))
+            .app_data(extractors::payload_config(config.payload_limit))
+            .app_data(extractors::path_config())
+            .app_data(extractors::query_config())
+            .configure(handlers::audit::config)
+            .configure(handlers::carts::config)
             .configure(handlers::categories::config)
+            .configure(handlers::orders::config)
             .configure(handlers::products::config)
             .configure(handlers::purchases::config)
+            .configure(handlers::questions::config)
             .configure(handlers::ratings::config)
+            .configure(handlers::tags::config)
             .configure(handlers::users::config)
+            .configure(handlers::warehouses::config)
+            .wrap_fn(audit::assign_request_id)
             .wrap(cors)
             .wrap(Logger::default())
     })
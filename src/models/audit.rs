@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct AuditLogModel {
+    pub id: Uuid,
+    pub actor: String,
+    pub action: String,
+    pub resource_type: String,
+    pub resource_id: Uuid,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    pub request_id: Uuid,
+    pub created_at: Option<DateTime<Utc>>
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditFilterOptions {
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    pub resource: Option<String>,
+    pub id: Option<Uuid>,
+    pub actor: Option<String>
+}
@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use sqlx::FromRow;
+use sqlx::types::BigDecimal;
+use crate::models::warehouses::Location;
+
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct CartItemModel {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub product_id: Uuid,
+    pub quantity: i32,
+    /// Warehouse holding the reserved units, while the reservation lasts.
+    pub warehouse_id: Option<Uuid>,
+    pub reserved_until: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>
+}
+
+/// A cart line priced from the product's current price.
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct CartLineModel {
+    pub product_id: Uuid,
+    pub product_name: String,
+    pub quantity: i32,
+    pub unit_price: BigDecimal,
+    pub total: BigDecimal,
+    pub reserved_until: Option<DateTime<Utc>>
+}
+
+
+#[derive(Debug, Deserialize)]
+pub struct SetCartItem {
+    pub product_id: Uuid,
+    pub quantity: i32
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CartItemPath {
+    pub id: Uuid,
+    pub product_id: Uuid
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Checkout {
+    pub ship_to: Option<Location>
+}
@@ -2,22 +2,48 @@ use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
+use crate::schema::Patch;
 
 #[derive(Debug, FromRow, Deserialize, Serialize)]
 pub struct CategoryModel {
     pub id: Uuid,
     pub category_name: String,
     pub created_at: Option<DateTime<Utc>>,
-    pub updated_at: Option<DateTime<Utc>>
+    pub updated_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub parent_id: Option<Uuid>
+}
+
+/// A category with the categories nested directly below it.
+#[derive(Debug, Serialize)]
+pub struct CategoryNode {
+    #[serde(flatten)]
+    pub category: CategoryModel,
+    pub children: Vec<CategoryNode>
 }
 
 #[derive(Debug, Deserialize)]
 pub struct CreateCategory {
-    pub category_name: String
+    pub category_name: String,
+    pub parent_id: Option<Uuid>
 }
 
 
 #[derive(Debug, Deserialize)]
 pub struct UpdateCategory {
-    pub category_name: Option<String>
+    #[serde(default)]
+    pub category_name: Patch<String>,
+    #[serde(default)]
+    pub parent_id: Patch<Uuid>
+}
+
+impl UpdateCategory {
+    /// Applies the patch on top of the stored row.
+    pub fn merge_into(self, category: CategoryModel) -> Result<CategoryModel, String> {
+        Ok(CategoryModel {
+            category_name: self.category_name.merge_required(category.category_name, "category_name")?,
+            parent_id: self.parent_id.merge(category.parent_id),
+            ..category
+        })
+    }
 }
\ No newline at end of file
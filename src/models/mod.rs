@@ -1,5 +1,11 @@
+pub mod audit;
+pub mod carts;
 pub mod categories;
+pub mod orders;
 pub mod products;
 pub mod purchases;
+pub mod questions;
 pub mod ratings;
-pub mod users;
\ No newline at end of file
+pub mod tags;
+pub mod users;
+pub mod warehouses;
\ No newline at end of file
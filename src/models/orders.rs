@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use sqlx::FromRow;
+use sqlx::types::BigDecimal;
+use crate::models::warehouses::Location;
+
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct OrderModel {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub currency: String,
+    pub total: BigDecimal,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct OrderItemModel {
+    pub id: Uuid,
+    pub order_id: Uuid,
+    pub product_id: Uuid,
+    pub quantity: i32,
+    pub unit_price: BigDecimal,
+    pub total: BigDecimal,
+    pub created_at: Option<DateTime<Utc>>,
+    pub warehouse_id: Uuid
+}
+
+/// An order together with its lines, as returned by the API.
+#[derive(Debug, Serialize)]
+pub struct OrderWithItems {
+    #[serde(flatten)]
+    pub order: OrderModel,
+    pub items: Vec<OrderItemModel>
+}
+
+
+#[derive(Debug, Deserialize)]
+pub struct CreateOrderItem {
+    pub product_id: Uuid,
+    pub quantity: i32
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateOrder {
+    pub user_id: Uuid,
+    pub items: Vec<CreateOrderItem>,
+    pub ship_to: Option<Location>
+}
\ No newline at end of file
@@ -4,6 +4,7 @@ use chrono::{DateTime, Utc};
 use uuid::Uuid;
 // use bigdecimal::BigDecimal;
 use sqlx::types::BigDecimal;
+use crate::{models::tags::TagMatch, schema::Patch};
 
 #[derive(Debug, FromRow, Deserialize, Serialize)]
 pub struct ProductModel {
@@ -13,6 +14,17 @@ pub struct ProductModel {
     pub category_id: Uuid,
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub stock_quantity: i32
+}
+
+/// A product as listed by `get_products`, with its rating average.
+#[derive(Debug, Serialize)]
+pub struct ProductListing {
+    #[serde(flatten)]
+    pub product: ProductModel,
+    pub rating_count: i64,
+    pub average_rating: Option<f64>
 }
 
 
@@ -20,20 +32,85 @@ pub struct ProductModel {
 pub struct CreateProduct {
     pub product_name: String,
     pub price: BigDecimal,
-    pub category_id: Uuid
+    pub category_id: Uuid,
+    pub stock_quantity: Option<i32>,
+    pub warehouse_id: Option<Uuid>
 }
 
 
 #[derive(Debug, Deserialize)]
 pub struct UpdateProduct {
-    pub product_name: Option<String>,
-    pub price: Option<BigDecimal>,
-    pub category_id: Option<Uuid>
+    #[serde(default)]
+    pub product_name: Patch<String>,
+    #[serde(default)]
+    pub price: Patch<BigDecimal>,
+    #[serde(default)]
+    pub category_id: Patch<Uuid>
+}
+
+impl UpdateProduct {
+    /// Applies the patch on top of the stored row.
+    pub fn merge_into(self, product: ProductModel) -> Result<ProductModel, String> {
+        Ok(ProductModel {
+            product_name: self.product_name.merge_required(product.product_name, "product_name")?,
+            price: self.price.merge_required(product.price, "price")?,
+            category_id: self.category_id.merge_required(product.category_id, "category_id")?,
+            ..product
+        })
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ProductFilterOptions {
     pub limit: Option<usize>,
     pub offset: Option<usize>,
-    pub category_id: Option<Uuid>
-}
\ No newline at end of file
+    pub category_id: Option<Uuid>,
+    /// With `category_id`, also list products of its subcategories.
+    pub include_descendants: Option<bool>,
+    pub include_deleted: Option<bool>,
+    /// Comma-separated tag names.
+    pub tags: Option<String>,
+    /// Defaults to `any`.
+    #[serde(rename = "match")]
+    pub tag_match: Option<TagMatch>
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct PriceHistoryModel {
+    pub id: Uuid,
+    pub product_id: Uuid,
+    pub price: BigDecimal,
+    pub valid_from: DateTime<Utc>,
+    pub valid_to: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PriceHistoryOptions {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PriceAsOfOptions {
+    pub at: Option<DateTime<Utc>>
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct StockAdjustmentModel {
+    pub id: Uuid,
+    pub product_id: Uuid,
+    pub delta: i32,
+    pub quantity_after: i32,
+    pub reason: String,
+    pub actor: String,
+    pub created_at: Option<DateTime<Utc>>,
+    pub warehouse_id: Uuid
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateStockAdjustment {
+    pub warehouse_id: Uuid,
+    pub delta: i32,
+    pub reason: String
+}
@@ -2,28 +2,73 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 use sqlx::FromRow;
+use sqlx::types::BigDecimal;
+use crate::{models::warehouses::Location, schema::Patch};
 
 
 #[derive(Debug, FromRow, Deserialize, Serialize)]
 pub struct PurchaseModel {
     pub id: Uuid,
     pub product_id: Uuid,
-    pub user_id: Uuid,
+    /// `None` for guest purchases that have not been claimed yet.
+    pub user_id: Option<Uuid>,
+    pub quantity: i32,
+    /// Product price captured when the purchase was made.
+    pub unit_price: BigDecimal,
+    pub currency: String,
+    /// `quantity * unit_price`, computed by the database.
+    pub total: BigDecimal,
+    pub status: String,
+    /// Warehouse the purchase is fulfilled from.
+    pub warehouse_id: Uuid,
     pub created_at: Option<DateTime<Utc>>,
-    pub updated_at: Option<DateTime<Utc>>
+    pub updated_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// Contact address for guest purchases. Purchases are publicly readable,
+    /// so it is only echoed to the guest at checkout, next to the claim token.
+    #[serde(skip_serializing, default)]
+    pub guest_email: Option<String>,
+    /// Handed to the guest once at checkout; never echoed back.
+    #[serde(skip_serializing, default)]
+    pub claim_token: Option<Uuid>
 }
 
 
+/// Exactly one of `user_id` and `guest_email` must be set.
 #[derive(Debug, Deserialize)]
 pub struct CreatePurchase {
     pub product_id: Uuid,
-    pub user_id: Uuid
+    pub user_id: Option<Uuid>,
+    pub guest_email: Option<String>,
+    pub quantity: Option<i32>,
+    pub ship_to: Option<Location>
 }
 
+/// `user_id` is accepted only so that attempts to change it can be rejected
+/// explicitly; guest purchases are attached through the claim flow.
 #[derive(Debug, Deserialize)]
 pub struct UpdatePurchase {
-    pub product_id: Option<Uuid>,
-    pub user_id: Option<Uuid>
+    #[serde(default)]
+    pub product_id: Patch<Uuid>,
+    #[serde(default)]
+    pub user_id: Patch<Uuid>,
+    #[serde(default)]
+    pub quantity: Patch<i32>
+}
+
+impl UpdatePurchase {
+    /// Applies the patch on top of the stored row.
+    pub fn merge_into(self, purchase: PurchaseModel) -> Result<PurchaseModel, String> {
+        if !matches!(self.user_id, Patch::Absent) {
+            return Err("user_id cannot be changed".to_string());
+        }
+
+        Ok(PurchaseModel {
+            product_id: self.product_id.merge_required(purchase.product_id, "product_id")?,
+            quantity: self.quantity.merge_required(purchase.quantity, "quantity")?,
+            ..purchase
+        })
+    }
 }
 
 
@@ -33,5 +78,81 @@ pub struct PurchaseFilterOptions {
     pub limit: Option<usize>,
     pub offset: Option<usize>,
     pub product_id: Option<Uuid>,
-    pub user_id: Option<Uuid>
+    pub user_id: Option<Uuid>,
+    pub include_deleted: Option<bool>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub status: Option<PurchaseStatus>
+}
+
+/// Lifecycle of a purchase:
+/// `pending -> paid -> shipped -> delivered`, with `cancelled` reachable
+/// before shipping and `refunded` once it has been paid for.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PurchaseStatus {
+    Pending,
+    Paid,
+    Shipped,
+    Delivered,
+    Cancelled,
+    Refunded
+}
+
+impl PurchaseStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PurchaseStatus::Pending => "pending",
+            PurchaseStatus::Paid => "paid",
+            PurchaseStatus::Shipped => "shipped",
+            PurchaseStatus::Delivered => "delivered",
+            PurchaseStatus::Cancelled => "cancelled",
+            PurchaseStatus::Refunded => "refunded"
+        }
+    }
+
+    pub fn parse(status: &str) -> Option<PurchaseStatus> {
+        match status {
+            "pending" => Some(PurchaseStatus::Pending),
+            "paid" => Some(PurchaseStatus::Paid),
+            "shipped" => Some(PurchaseStatus::Shipped),
+            "delivered" => Some(PurchaseStatus::Delivered),
+            "cancelled" => Some(PurchaseStatus::Cancelled),
+            "refunded" => Some(PurchaseStatus::Refunded),
+            _ => None
+        }
+    }
+
+    pub fn can_transition_to(&self, next: PurchaseStatus) -> bool {
+        use PurchaseStatus::*;
+
+        matches!(
+            (self, next),
+            (Pending, Paid)
+                | (Paid, Shipped)
+                | (Shipped, Delivered)
+                | (Pending, Cancelled)
+                | (Paid, Cancelled)
+                | (Paid, Refunded)
+                | (Shipped, Refunded)
+                | (Delivered, Refunded)
+        )
+    }
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct PurchaseTransitionModel {
+    pub id: Uuid,
+    pub purchase_id: Uuid,
+    pub from_status: String,
+    pub to_status: String,
+    pub actor: String,
+    pub reason: Option<String>,
+    pub created_at: Option<DateTime<Utc>>
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreatePurchaseTransition {
+    pub status: PurchaseStatus,
+    pub reason: Option<String>
 }
\ No newline at end of file
@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use sqlx::FromRow;
+
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct QuestionModel {
+    pub id: Uuid,
+    pub product_id: Uuid,
+    pub user_id: Uuid,
+    pub body: String,
+    pub upvotes: i32,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct AnswerModel {
+    pub id: Uuid,
+    pub question_id: Uuid,
+    pub user_id: Uuid,
+    pub body: String,
+    /// Set when the author had bought the product at the time of answering.
+    pub verified_buyer: bool,
+    /// Set when the answer was posted with admin credentials.
+    pub staff: bool,
+    pub upvotes: i32,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>
+}
+
+/// A question as listed under a product, with its answers.
+#[derive(Debug, Serialize)]
+pub struct QuestionThread {
+    #[serde(flatten)]
+    pub question: QuestionModel,
+    pub answers: Vec<AnswerModel>
+}
+
+/// Body of `POST /products/{id}/questions` and `POST /questions/{id}/answers`.
+#[derive(Debug, Deserialize)]
+pub struct CreatePost {
+    pub body: String
+}
@@ -1,7 +1,9 @@
+use std::collections::BTreeMap;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 use sqlx::FromRow;
+use crate::schema::Patch;
 
 
 #[derive(Debug, FromRow, Deserialize, Serialize)]
@@ -11,7 +13,21 @@ pub struct RatingModel {
     pub product_id: Uuid,
     pub user_id: Uuid,
     pub created_at: Option<DateTime<Utc>>,
-    pub updated_at: Option<DateTime<Utc>>
+    pub updated_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// Set when the user had bought the product at the time of rating.
+    pub verified_purchase: bool,
+    pub title: Option<String>,
+    pub body: Option<String>,
+    pub moderation_status: String,
+    pub moderation_reason: Option<String>,
+    pub moderated_at: Option<DateTime<Utc>>,
+    pub helpful_count: i32,
+    pub unhelpful_count: i32,
+    /// Wilson lower bound of the helpful share; derived from the two counts.
+    pub helpful_score: f64,
+    /// Abuse rules that fired when the rating was created.
+    pub abuse_flags: Vec<String>
 }
 
 
@@ -19,14 +35,52 @@ pub struct RatingModel {
 pub struct CreateRating {
     pub rating: i32,
     pub product_id: Uuid,
-    pub user_id: Uuid
+    pub user_id: Uuid,
+    pub title: Option<String>,
+    pub body: Option<String>
 }
 
+/// `product_id` and `user_id` are accepted only so that attempts to change
+/// them can be rejected explicitly.
 #[derive(Debug, Deserialize)]
 pub struct UpdateRating {
-    pub rating: Option<i32>,
-    pub product_id: Option<Uuid>,
-    pub user_id: Option<Uuid>
+    #[serde(default)]
+    pub rating: Patch<i32>,
+    #[serde(default)]
+    pub product_id: Patch<Uuid>,
+    #[serde(default)]
+    pub user_id: Patch<Uuid>,
+    #[serde(default)]
+    pub title: Patch<String>,
+    #[serde(default)]
+    pub body: Patch<String>
+}
+
+impl UpdateRating {
+    /// Applies the patch on top of the stored row.
+    pub fn merge_into(self, rating: RatingModel) -> Result<RatingModel, String> {
+        if !matches!(self.product_id, Patch::Absent) {
+            return Err("product_id cannot be changed".to_string());
+        }
+        if !matches!(self.user_id, Patch::Absent) {
+            return Err("user_id cannot be changed".to_string());
+        }
+
+        Ok(RatingModel {
+            rating: self.rating.merge_required(rating.rating, "rating")?,
+            title: self.title.merge(rating.title),
+            body: self.body.merge(rating.body),
+            ..rating
+        })
+    }
+}
+
+/// Body of `PUT /products/{id}/ratings/me`.
+#[derive(Debug, Deserialize)]
+pub struct PutRating {
+    pub rating: i32,
+    pub title: Option<String>,
+    pub body: Option<String>
 }
 
 
@@ -36,5 +90,180 @@ pub struct RatingFilterOptions {
     pub limit: Option<usize>,
     pub offset: Option<usize>,
     pub product_id: Option<Uuid>,
-    pub user_id: Option<Uuid>
-}
\ No newline at end of file
+    pub user_id: Option<Uuid>,
+    pub include_deleted: Option<bool>,
+    pub verified_purchase: Option<bool>,
+    /// Admin only; everyone else sees approved ratings.
+    pub moderation_status: Option<ModerationStatus>,
+    pub sort: Option<RatingSort>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RatingSort {
+    /// Oldest first.
+    Created,
+    /// Highest Wilson score first.
+    Helpful
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModerationStatus {
+    Pending,
+    Approved,
+    Rejected
+}
+
+impl ModerationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ModerationStatus::Pending => "pending",
+            ModerationStatus::Approved => "approved",
+            ModerationStatus::Rejected => "rejected"
+        }
+    }
+
+    pub fn parse(status: &str) -> Option<ModerationStatus> {
+        match status {
+            "pending" => Some(ModerationStatus::Pending),
+            "approved" => Some(ModerationStatus::Approved),
+            "rejected" => Some(ModerationStatus::Rejected),
+            _ => None
+        }
+    }
+}
+
+/// An admin's decision on a queued rating.
+#[derive(Debug, Deserialize)]
+pub struct ModerateRating {
+    pub status: ModerationStatus,
+    pub reason: Option<String>
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct RatingVoteModel {
+    pub id: Uuid,
+    pub rating_id: Uuid,
+    pub user_id: Uuid,
+    pub vote: String,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Vote {
+    Up,
+    Down
+}
+
+impl Vote {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Vote::Up => "up",
+            Vote::Down => "down"
+        }
+    }
+
+    pub fn parse(vote: &str) -> Option<Vote> {
+        match vote {
+            "up" => Some(Vote::Up),
+            "down" => Some(Vote::Down),
+            _ => None
+        }
+    }
+
+    /// Change to `(helpful_count, unhelpful_count)` for one vote of this kind.
+    pub fn counts(&self) -> (i32, i32) {
+        match self {
+            Vote::Up => (1, 0),
+            Vote::Down => (0, 1)
+        }
+    }
+}
+
+/// Body of `POST /ratings/{id}/votes`.
+#[derive(Debug, Deserialize)]
+pub struct CastVote {
+    pub vote: Vote
+}
+
+/// Aggregate of the approved ratings of a product or category.
+#[derive(Debug, Serialize)]
+pub struct RatingSummary {
+    pub count: i64,
+    pub mean: Option<f64>,
+    /// Number of ratings per star, 1 through 5.
+    pub histogram: BTreeMap<i32, i64>,
+    /// Mean pulled towards `prior_mean` by `prior_weight` virtual ratings, so
+    /// a handful of ratings cannot top the catalogue.
+    pub bayesian_score: f64
+}
+
+impl RatingSummary {
+    pub fn new(histogram: [i64; 5], prior_mean: f64, prior_weight: f64) -> RatingSummary {
+        let count: i64 = histogram.iter().sum();
+        let sum: i64 = histogram.iter().zip(1..).map(|(stars, rating)| stars * rating).sum();
+
+        RatingSummary {
+            count,
+            mean: (count > 0).then(|| sum as f64 / count as f64),
+            histogram: (1..).zip(histogram).collect(),
+            bayesian_score: if count > 0 {
+                (prior_weight * prior_mean + sum as f64) / (prior_weight + count as f64)
+            } else {
+                prior_mean
+            }
+        }
+    }
+}
+
+/// Flagged ratings on one product within the same hour.
+#[derive(Debug, FromRow, Serialize)]
+pub struct AbuseClusterModel {
+    pub product_id: Uuid,
+    pub window_start: DateTime<Utc>,
+    pub rating_count: i64,
+    pub flags: Vec<String>,
+    pub rating_ids: Vec<Uuid>,
+    pub user_ids: Vec<Uuid>
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(actual: f64, expected: f64) -> bool {
+        (actual - expected).abs() < 1e-4
+    }
+
+    #[test]
+    fn summary_without_ratings_falls_back_to_the_prior() {
+        let summary = RatingSummary::new([0; 5], 3.5, 10.0);
+
+        assert_eq!(summary.count, 0);
+        assert_eq!(summary.mean, None);
+        assert_eq!(summary.histogram, (1..=5).map(|stars| (stars, 0)).collect());
+        assert_eq!(summary.bayesian_score, 3.5);
+    }
+
+    #[test]
+    fn summary_pulls_few_ratings_towards_the_prior() {
+        let summary = RatingSummary::new([0, 0, 0, 0, 2], 3.0, 8.0);
+
+        assert_eq!(summary.count, 2);
+        assert_eq!(summary.mean, Some(5.0));
+        assert_eq!(summary.histogram.get(&5), Some(&2));
+        assert!(close(summary.bayesian_score, 3.4));
+    }
+
+    #[test]
+    fn summary_mean_weighs_each_star() {
+        let summary = RatingSummary::new([1, 0, 1, 0, 2], 3.0, 0.0);
+
+        assert_eq!(summary.count, 4);
+        assert_eq!(summary.mean, Some(3.5));
+        assert_eq!(summary.bayesian_score, 3.5);
+    }
+}
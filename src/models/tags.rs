@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct TagModel {
+    pub id: Uuid,
+    pub tag_name: String,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTag {
+    pub tag_name: String
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProductTagPath {
+    pub id: Uuid,
+    pub tag_id: Uuid
+}
+
+/// Whether `get_products?tags=` needs every listed tag or any one of them.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TagMatch {
+    All,
+    Any
+}
+
+/// Normalizes a tag name the way it is stored.
+pub fn normalize(tag_name: &str) -> String {
+    tag_name.trim().to_lowercase()
+}
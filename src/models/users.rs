@@ -2,6 +2,7 @@ use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
+use crate::schema::Patch;
 
 
 #[derive(Debug, FromRow, Deserialize, Serialize)]
@@ -10,7 +11,10 @@ pub struct UserModel {
     pub username: String,
     pub email: Option<String>,
     pub created_at: Option<DateTime<Utc>>,
-    pub updated_at: Option<DateTime<Utc>>
+    pub updated_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// Cleared whenever the email changes.
+    pub email_verified_at: Option<DateTime<Utc>>
 }
 
 #[derive(Debug, Deserialize)]
@@ -22,6 +26,29 @@ pub struct CreateUser {
 
 #[derive(Debug, Deserialize)]
 pub struct UpdateUser {
-    pub username: Option<String>,
-    pub email: Option<String>
-}
\ No newline at end of file
+    #[serde(default)]
+    pub username: Patch<String>,
+    #[serde(default)]
+    pub email: Patch<String>
+}
+
+impl UpdateUser {
+    /// Applies the patch on top of the stored row.
+    pub fn merge_into(self, user: UserModel) -> Result<UserModel, String> {
+        Ok(UserModel {
+            username: self.username.merge_required(user.username, "username")?,
+            email: self.email.merge(user.email).filter(|email| !email.trim().is_empty()),
+            ..user
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConfirmEmail {
+    pub token: Uuid
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClaimPurchase {
+    pub claim_token: Uuid
+}
@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use crate::schema::Patch;
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct WarehouseModel {
+    pub id: Uuid,
+    pub warehouse_name: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWarehouse {
+    pub warehouse_name: String,
+    pub latitude: f64,
+    pub longitude: f64
+}
+
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateWarehouse {
+    #[serde(default)]
+    pub warehouse_name: Patch<String>,
+    #[serde(default)]
+    pub latitude: Patch<f64>,
+    #[serde(default)]
+    pub longitude: Patch<f64>
+}
+
+impl UpdateWarehouse {
+    /// Applies the patch on top of the stored row.
+    pub fn merge_into(self, warehouse: WarehouseModel) -> Result<WarehouseModel, String> {
+        Ok(WarehouseModel {
+            warehouse_name: self.warehouse_name.merge_required(warehouse.warehouse_name, "warehouse_name")?,
+            latitude: self.latitude.merge_required(warehouse.latitude, "latitude")?,
+            longitude: self.longitude.merge_required(warehouse.longitude, "longitude")?,
+            ..warehouse
+        })
+    }
+}
+
+/// A delivery address, used to pick the nearest warehouse.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64
+}
+
+/// Stock held for one product at one warehouse.
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct WarehouseStockModel {
+    pub warehouse_id: Uuid,
+    pub warehouse_name: String,
+    pub quantity: i32
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct StockTransferModel {
+    pub id: Uuid,
+    pub product_id: Uuid,
+    pub from_warehouse_id: Uuid,
+    pub to_warehouse_id: Uuid,
+    pub quantity: i32,
+    pub reason: Option<String>,
+    pub actor: String,
+    pub created_at: Option<DateTime<Utc>>
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateStockTransfer {
+    pub from_warehouse_id: Uuid,
+    pub to_warehouse_id: Uuid,
+    pub quantity: i32,
+    pub reason: Option<String>
+}
@@ -0,0 +1,182 @@
+use std::str::FromStr;
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+use crate::models::ratings::ModerationStatus;
+
+
+/// What happens to a rating from a user who never bought the product.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PurchasePolicy {
+    Reject,
+    /// Accept the rating with `verified_purchase = false`.
+    Flag
+}
+
+impl FromStr for PurchasePolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "reject" => Ok(PurchasePolicy::Reject),
+            "flag" => Ok(PurchasePolicy::Flag),
+            _ => Err(format!("unknown purchase policy: {}", value))
+        }
+    }
+}
+
+/// What happens to a new rating that trips an abuse rule.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AbuseAction {
+    /// Publish it, recording the rules in `abuse_flags`.
+    Flag,
+    /// Also hold it in the moderation queue.
+    Quarantine
+}
+
+impl FromStr for AbuseAction {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "flag" => Ok(AbuseAction::Flag),
+            "quarantine" => Ok(AbuseAction::Quarantine),
+            _ => Err(format!("unknown abuse action: {}", value))
+        }
+    }
+}
+
+/// Thresholds for the abuse rules run on new ratings; `0` disables a rule.
+#[derive(Debug, Clone)]
+pub struct AbuseRules {
+    /// Accounts younger than this are flagged as `new_account`.
+    pub min_account_age_hours: i32,
+    /// Users who already rated this many times in the past hour are flagged
+    /// as `velocity`.
+    pub max_ratings_per_hour: i64,
+    /// Products that already received this many ratings within
+    /// `spike_window_mins` are flagged as `product_spike`.
+    pub spike_threshold: i64,
+    pub spike_window_mins: i32,
+    pub action: AbuseAction
+}
+
+/// Names of the abuse rules a new rating by `user_id` on `product_id` trips.
+/// Deleted ratings still count towards velocity and spikes.
+pub async fn abuse_flags<'e, E>(executor: E, rules: &AbuseRules, user_id: Uuid, product_id: Uuid) -> Result<Vec<String>, sqlx::Error>
+where
+    E: PgExecutor<'e>
+{
+    let activity = sqlx::query!(
+        r#"SELECT
+                (SELECT created_at > NOW() - $3::INT * INTERVAL '1 hour' FROM users WHERE id = $1) AS new_account,
+                (SELECT COUNT(*) FROM ratings WHERE user_id = $1 AND created_at > NOW() - INTERVAL '1 hour') AS "user_ratings!",
+                (SELECT COUNT(*) FROM ratings WHERE product_id = $2 AND created_at > NOW() - $4::INT * INTERVAL '1 minute') AS "product_ratings!""#,
+        user_id,
+        product_id,
+        rules.min_account_age_hours,
+        rules.spike_window_mins
+    )
+        .fetch_one(executor)
+        .await?;
+
+    let mut flags = Vec::new();
+    if rules.min_account_age_hours > 0 && activity.new_account.unwrap_or(false) {
+        flags.push("new_account".to_string());
+    }
+    if rules.max_ratings_per_hour > 0 && activity.user_ratings >= rules.max_ratings_per_hour {
+        flags.push("velocity".to_string());
+    }
+    if rules.spike_threshold > 0 && rules.spike_window_mins > 0 && activity.product_ratings >= rules.spike_threshold {
+        flags.push("product_spike".to_string());
+    }
+    Ok(flags)
+}
+
+/// Moves a flagged rating into the queue when the rules call for quarantine,
+/// keeping any reason already given.
+pub fn quarantine(
+    rules: &AbuseRules,
+    flags: &[String],
+    status: ModerationStatus,
+    reason: Option<String>
+) -> (ModerationStatus, Option<String>) {
+    if flags.is_empty() || rules.action != AbuseAction::Quarantine {
+        return (status, reason);
+    }
+
+    let abuse = format!("Suspected abuse: {}", flags.join(", "));
+    let reason = match reason {
+        Some(reason) => format!("{}; {}", reason, abuse),
+        None => abuse
+    };
+    (ModerationStatus::Pending, Some(reason))
+}
+
+/// Whether the user bought the product, either directly or as an order line.
+/// Cancelled purchases do not count.
+pub async fn has_purchased<'e, E>(executor: E, user_id: Uuid, product_id: Uuid) -> Result<bool, sqlx::Error>
+where
+    E: PgExecutor<'e>
+{
+    sqlx::query_scalar!(
+        r#"SELECT EXISTS (
+                SELECT 1 FROM purchases
+                WHERE user_id = $1 AND product_id = $2 AND deleted_at IS NULL AND status <> 'cancelled'
+            ) OR EXISTS (
+                SELECT 1 FROM order_items i JOIN orders o ON o.id = i.order_id
+                WHERE o.user_id = $1 AND i.product_id = $2
+            ) AS "purchased!""#,
+        user_id,
+        product_id
+    )
+        .fetch_one(executor)
+        .await
+}
+
+const MAX_TITLE_LENGTH: usize = 200;
+
+pub fn validate_title(title: Option<&str>) -> Result<(), String> {
+    match title {
+        Some(title) if title.chars().count() > MAX_TITLE_LENGTH => {
+            Err(format!("title must be at most {} characters", MAX_TITLE_LENGTH))
+        },
+        _ => Ok(())
+    }
+}
+
+/// Banned words found in the review text, matched case-insensitively
+/// against whole words.
+pub fn banned_words_in(banned: &[String], texts: &[Option<&str>]) -> Vec<String> {
+    let mut found: Vec<String> = texts
+        .iter()
+        .flatten()
+        .flat_map(|text| text.split(|c: char| !c.is_alphanumeric()))
+        .map(str::to_lowercase)
+        .filter(|word| banned.contains(word))
+        .collect();
+    found.sort();
+    found.dedup();
+    found
+}
+
+/// Moderation state for a review whose text was just written. Flagged text
+/// goes back to the queue, and so does a rewrite of a rejected review;
+/// otherwise the current state is kept.
+pub fn review_status(
+    banned: &[String],
+    current: ModerationStatus,
+    current_reason: Option<String>,
+    title: Option<&str>,
+    body: Option<&str>
+) -> (ModerationStatus, Option<String>) {
+    let found = banned_words_in(banned, &[title, body]);
+    if !found.is_empty() {
+        return (ModerationStatus::Pending, Some(format!("Contains banned words: {}", found.join(", "))));
+    }
+
+    match current {
+        ModerationStatus::Rejected => (ModerationStatus::Pending, Some("Edited after rejection".to_string())),
+        status => (status, current_reason)
+    }
+}
@@ -0,0 +1,104 @@
+use std::fmt;
+
+use actix_web::{guard::GuardContext, http::{header, StatusCode}};
+use json_patch::{Patch, PatchErrorKind, PatchOperation};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+
+/// Columns that no patch document may touch.
+pub const IMMUTABLE_FIELDS: [&str; 4] = ["id", "created_at", "updated_at", "deleted_at"];
+
+#[derive(Debug)]
+pub enum PatchError {
+    /// The document addresses a field that cannot be modified or does not exist.
+    Forbidden(String),
+    /// A `test` operation did not match the stored value.
+    TestFailed(String),
+    /// The document is malformed or produces an invalid resource.
+    Invalid(String)
+}
+
+impl PatchError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            PatchError::Forbidden(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            PatchError::TestFailed(_) => StatusCode::CONFLICT,
+            PatchError::Invalid(_) => StatusCode::UNPROCESSABLE_ENTITY
+        }
+    }
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::Forbidden(message) => write!(f, "{}", message),
+            PatchError::TestFailed(message) => write!(f, "{}", message),
+            PatchError::Invalid(message) => write!(f, "{}", message)
+        }
+    }
+}
+
+/// Route guard selecting handlers for `Content-Type: application/json-patch+json`.
+pub fn is_json_patch(ctx: &GuardContext) -> bool {
+    ctx.head()
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.split(';').next().unwrap_or("").trim().eq_ignore_ascii_case("application/json-patch+json"))
+        .unwrap_or(false)
+}
+
+/// Applies an RFC 6902 patch document to `resource`.
+///
+/// Either every operation succeeds or `resource` is left as it was. Operations
+/// may only address existing top-level fields outside of `immutable`.
+pub fn apply<T>(resource: &T, patch: &Patch, immutable: &[&str]) -> Result<T, PatchError>
+where
+    T: Serialize + DeserializeOwned
+{
+    let mut document = serde_json::to_value(resource)
+        .map_err(|err| PatchError::Invalid(format!("{}", err)))?;
+
+    for operation in &patch.0 {
+        let (path, from) = match operation {
+            PatchOperation::Add(op) => (&op.path, None),
+            PatchOperation::Remove(op) => (&op.path, None),
+            PatchOperation::Replace(op) => (&op.path, None),
+            PatchOperation::Move(op) => (&op.path, Some(&op.from)),
+            PatchOperation::Copy(op) => (&op.path, Some(&op.from)),
+            PatchOperation::Test(op) => (&op.path, None)
+        };
+        let is_test = matches!(operation, PatchOperation::Test(_));
+
+        check_path(&document, path, immutable, is_test)?;
+        if let Some(from) = from {
+            // The source of a move is removed, so it must be writable too.
+            let is_copy = matches!(operation, PatchOperation::Copy(_));
+            check_path(&document, from, immutable, is_copy)?;
+        }
+    }
+
+    json_patch::patch(&mut document, &patch.0).map_err(|err| match err.kind {
+        PatchErrorKind::TestFailed => PatchError::TestFailed(format!("Precondition failed at {}", err.path)),
+        _ => PatchError::Invalid(format!("Operation {} failed: {}", err.operation, err))
+    })?;
+
+    serde_json::from_value(document).map_err(|err| PatchError::Invalid(format!("Patched resource is invalid: {}", err)))
+}
+
+fn check_path(document: &Value, path: &str, immutable: &[&str], read_only: bool) -> Result<(), PatchError> {
+    let field = match path.strip_prefix('/') {
+        Some(pointer) => pointer.split('/').next().unwrap_or(""),
+        None => return Err(PatchError::Forbidden(format!("Path '{}' must address a field", path)))
+    };
+
+    if document.get(field).is_none() {
+        return Err(PatchError::Forbidden(format!("Unknown field '{}'", field)));
+    }
+    if !read_only && immutable.contains(&field) {
+        return Err(PatchError::Forbidden(format!("Field '{}' cannot be modified", field)));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,38 @@
+use chrono::{DateTime, Utc};
+use sqlx::{types::BigDecimal, PgExecutor, Postgres, Transaction};
+use uuid::Uuid;
+
+
+/// Opens a new price period for a product, starting at `valid_from`. Callers
+/// only record actual changes; the previous period ends where this one starts.
+pub async fn record_price(
+    tx: &mut Transaction<'_, Postgres>,
+    product_id: Uuid,
+    price: &BigDecimal,
+    valid_from: DateTime<Utc>
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO product_price_history (product_id, price, valid_from) VALUES ($1, $2, $3)",
+        product_id,
+        price,
+        valid_from
+    )
+        .execute(tx)
+        .await?;
+
+    Ok(())
+}
+
+/// The price a product had at `at`, or `None` if it was not yet listed.
+pub async fn price_as_of<'e, E>(executor: E, product_id: Uuid, at: DateTime<Utc>) -> Result<Option<BigDecimal>, sqlx::Error>
+where
+    E: PgExecutor<'e>
+{
+    sqlx::query_scalar!(
+        "SELECT price FROM product_price_history WHERE product_id = $1 AND valid_from <= $2 ORDER BY valid_from DESC LIMIT 1",
+        product_id,
+        at
+    )
+        .fetch_optional(executor)
+        .await
+}
@@ -1,14 +1,95 @@
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
 use uuid::Uuid;
 
 
 #[derive(Debug, Deserialize)]
 pub struct FilterOptions {
     pub limit: Option<usize>,
-    pub offset: Option<usize>
+    pub offset: Option<usize>,
+    pub include_deleted: Option<bool>
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeletedOptions {
+    pub include_deleted: Option<bool>
+}
+
+/// How a delete treats rows that still reference the deleted one.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeleteStrategy {
+    /// Refuse with 409 while live dependents exist.
+    Restrict,
+    /// Move dependents to the row given by `reassign_to`.
+    Reassign,
+    /// Soft-delete dependents along with the row.
+    Cascade
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteOptions {
+    pub strategy: Option<DeleteStrategy>,
+    pub reassign_to: Option<Uuid>
+}
+
+impl DeleteOptions {
+    /// `reassign_to` on its own implies `strategy=reassign`; otherwise deletes
+    /// are restricted unless asked to cascade.
+    pub fn strategy(&self) -> DeleteStrategy {
+        match (self.strategy, self.reassign_to) {
+            (Some(strategy), _) => strategy,
+            (None, Some(_)) => DeleteStrategy::Reassign,
+            (None, None) => DeleteStrategy::Restrict
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct PathOptions {
     pub id: Uuid
-}
\ No newline at end of file
+}
+
+/// A single member of a JSON Merge Patch (RFC 7396) body.
+///
+/// A missing key leaves the stored value untouched, `null` clears it and any
+/// other value replaces it. Fields must be marked `#[serde(default)]` so that
+/// missing keys deserialize as `Absent`.
+#[derive(Debug, Clone, Default)]
+pub enum Patch<T> {
+    #[default]
+    Absent,
+    Null,
+    Value(T)
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Patch<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>
+    {
+        match Option::<T>::deserialize(deserializer)? {
+            Some(value) => Ok(Patch::Value(value)),
+            None => Ok(Patch::Null)
+        }
+    }
+}
+
+impl<T> Patch<T> {
+    /// Merges the patch into a nullable column.
+    pub fn merge(self, current: Option<T>) -> Option<T> {
+        match self {
+            Patch::Absent => current,
+            Patch::Null => None,
+            Patch::Value(value) => Some(value)
+        }
+    }
+
+    /// Merges the patch into a `NOT NULL` column, rejecting `null`.
+    pub fn merge_required(self, current: T, field: &str) -> Result<T, String> {
+        match self {
+            Patch::Absent => Ok(current),
+            Patch::Null => Err(format!("{} cannot be null", field)),
+            Patch::Value(value) => Ok(value)
+        }
+    }
+}